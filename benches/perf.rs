@@ -26,6 +26,7 @@ struct TestDefinition {
 }
 
 #[derive(Clone, Debug)]
+#[allow(dead_code)]
 struct TestResult {
     mean: f64,
     stddev: f64,
@@ -75,7 +76,7 @@ fn make_tests(input_path: &str) -> Vec<TestDefinition> {
     vec![
         TestDefinition {
             cmd: format!("{} {}", our_path, input_path),
-            name: our_version.into(),
+            name: our_version,
         },
         TestDefinition {
             cmd: format!("python3 -m aggregate6 {}", input_path),
@@ -99,7 +100,7 @@ fn make_v4_tests(input_path: &str) -> Vec<TestDefinition> {
 
     all_tests.push(TestDefinition {
         cmd: format!("iprange --optimize {}", input_path),
-        name: iprange_version.lines().nth(0).unwrap().into(),
+        name: iprange_version.lines().next().unwrap().into(),
     });
 
     all_tests
@@ -109,7 +110,7 @@ fn make_v4_tests(input_path: &str) -> Vec<TestDefinition> {
 // generate prefixes with length 8->24 so some should be possible.
 fn make_random_prefix(rng: &mut impl Rng) -> Ipv4Net {
     let prefix_len: u8 = rng.gen_range(8..25);
-    let netaddr: u32 = rng.gen_range(0..(1 << prefix_len)) << 32 - prefix_len;
+    let netaddr: u32 = rng.gen_range(0..(1 << prefix_len)) << (32 - prefix_len);
 
     Ipv4Net::new(netaddr.into(), prefix_len).unwrap()
 }
@@ -158,13 +159,12 @@ where
         .expect("Can't read results");
     resultfile.close().unwrap();
 
-    let hf_result = json::parse(&String::from_utf8_lossy(&raw_result_buf)).expect(
-        format!(
+    let hf_result = json::parse(&String::from_utf8_lossy(&raw_result_buf)).unwrap_or_else(|_| {
+        panic!(
             "Can't parse hyperfine json results from command `{}`",
             cmd.as_ref().to_string_lossy()
         )
-        .as_str(),
-    );
+    });
 
     let final_result = &hf_result["results"][0];
 
@@ -172,7 +172,7 @@ where
 }
 
 fn plot_results(
-    results: &Vec<(TestDefinition, TestResult)>,
+    results: &[(TestDefinition, TestResult)],
     caption: &str,
     outfile: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -0,0 +1,234 @@
+//! Lossy aggregation: merging prefixes into covering supernets until a list
+//! fits in a maximum number of entries.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::ranges::{self, Prefix};
+
+/// A prefix in the working list, linked to its neighbours in address order.
+struct Entry {
+    first: u128,
+    last: u128,
+    len: u8,
+    /// Bumped whenever the entry changes, invalidating queued merges using it
+    version: u32,
+    alive: bool,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// A queued merge of two neighbouring entries into their common supernet.
+/// Ordered by added address space first, so the heap yields the cheapest.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Merge {
+    cost: u128,
+    first: u128,
+    last: u128,
+    len: u8,
+    left: (usize, u32),
+    right: (usize, u32),
+}
+
+/// The smallest prefix covering both `a` and `b`, as `(first, last, len)`.
+fn supernet<P: Prefix>(a: &Entry, b: &Entry) -> (u128, u128, u8) {
+    let common = (a.first ^ b.first).leading_zeros() - (128 - u32::from(P::BITS));
+    let len = common.min(a.len.into()).min(b.len.into()) as u8;
+    let (first, last) = ranges::block::<P>(a.first, len);
+    (first, last, len)
+}
+
+struct Budget {
+    entries: Vec<Entry>,
+    queue: BinaryHeap<Reverse<Merge>>,
+}
+
+impl Budget {
+    /// The merge of entry `left` with the entry following it, if any.
+    fn merge_for<P: Prefix>(&self, left: usize) -> Option<Merge> {
+        let right = self.entries[left].next?;
+        let (a, b) = (&self.entries[left], &self.entries[right]);
+        let (first, last, len) = supernet::<P>(a, b);
+        // Everything already covered inside the supernet is a contiguous run
+        // of entries around the pair, so walk outwards to total it.
+        let mut covered = (a.last - a.first) + (b.last - b.first) + 2;
+        let mut cur = a.prev;
+        while let Some(i) = cur.filter(|&i| self.entries[i].first >= first) {
+            covered += self.entries[i].last - self.entries[i].first + 1;
+            cur = self.entries[i].prev;
+        }
+        cur = b.next;
+        while let Some(i) = cur.filter(|&i| self.entries[i].last <= last) {
+            covered += self.entries[i].last - self.entries[i].first + 1;
+            cur = self.entries[i].next;
+        }
+        // Written this way round to avoid overflow for ::/0
+        let cost = (last - first) - (covered - 1);
+        Some(Merge {
+            cost,
+            first,
+            last,
+            len,
+            left: (left, a.version),
+            right: (right, b.version),
+        })
+    }
+
+    fn propose<P: Prefix>(&mut self, left: usize) {
+        if let Some(merge) = self.merge_for::<P>(left) {
+            self.queue.push(Reverse(merge));
+        }
+    }
+
+    fn is_current(&self, (i, version): (usize, u32)) -> bool {
+        self.entries[i].alive && self.entries[i].version == version
+    }
+
+    /// Replace every entry inside the supernet of `merge` with the supernet,
+    /// returning the entry now holding it and the number of entries removed.
+    fn apply(&mut self, merge: &Merge) -> (usize, usize) {
+        let mut start = merge.left.0;
+        while let Some(i) = self.entries[start]
+            .prev
+            .filter(|&i| self.entries[i].first >= merge.first)
+        {
+            start = i;
+        }
+        let mut removed = 0;
+        let mut next = self.entries[start].next;
+        while let Some(i) = next.filter(|&i| self.entries[i].first <= merge.last) {
+            self.entries[i].alive = false;
+            next = self.entries[i].next;
+            removed += 1;
+        }
+        let entry = &mut self.entries[start];
+        entry.first = merge.first;
+        entry.last = merge.last;
+        entry.len = merge.len;
+        entry.version += 1;
+        entry.next = next;
+        if let Some(i) = next {
+            self.entries[i].prev = Some(start);
+        }
+        (start, removed)
+    }
+}
+
+/// Merge `nets` into covering supernets, always choosing the merge that adds
+/// the fewest addresses, until at most `max` prefixes remain.
+///
+/// Returns the resulting prefixes, aggregated, and the number of addresses
+/// that were added.
+pub(crate) fn fit<P: Prefix>(nets: &[P], max: usize) -> (Vec<P>, u128) {
+    let nets: Vec<P> = ranges::to_prefixes(&ranges::to_ranges(nets));
+    if nets.len() <= max.max(1) {
+        return (nets, 0);
+    }
+    let mut budget = Budget {
+        entries: nets
+            .iter()
+            .enumerate()
+            .map(|(i, net)| Entry {
+                first: net.first(),
+                last: net.last(),
+                len: net.len(),
+                version: 0,
+                alive: true,
+                prev: i.checked_sub(1),
+                next: Some(i + 1).filter(|&n| n < nets.len()),
+            })
+            .collect(),
+        queue: BinaryHeap::new(),
+    };
+    for i in 0..budget.entries.len() {
+        budget.propose::<P>(i);
+    }
+
+    let mut count = nets.len();
+    let mut added = 0;
+    while count > max.max(1) {
+        let Reverse(merge) = budget
+            .queue
+            .pop()
+            .expect("a list of two or more can be merged");
+        if !budget.is_current(merge.left)
+            || !budget.is_current(merge.right)
+            || budget.entries[merge.left.0].next != Some(merge.right.0)
+        {
+            continue;
+        }
+        // Other entries inside the supernet may have grown since this was
+        // queued, making it cheaper
+        let fresh = budget.merge_for::<P>(merge.left.0).unwrap();
+        if fresh.cost != merge.cost {
+            budget.queue.push(Reverse(fresh));
+            continue;
+        }
+        let (merged, removed) = budget.apply(&merge);
+        count -= removed;
+        added += merge.cost;
+        if let Some(prev) = budget.entries[merged].prev {
+            budget.propose::<P>(prev);
+        }
+        budget.propose::<P>(merged);
+    }
+
+    // The head of the list never moves, as merges are stored in the leftmost
+    // entry they cover
+    let mut kept: Vec<P> = Vec::with_capacity(count);
+    let mut cur = Some(0);
+    while let Some(i) = cur {
+        let entry = &budget.entries[i];
+        kept.push(P::from_bits(entry.first, entry.len));
+        cur = entry.next;
+    }
+    // A supernet may have completed a pair that can be joined exactly
+    (ranges::to_prefixes(&ranges::to_ranges(&kept)), added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipnet::{Ipv4Net, Ipv6Net};
+
+    fn v4(nets: &[&str]) -> Vec<Ipv4Net> {
+        nets.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn already_fits() {
+        let nets = v4(&["10.0.0.0/24", "10.0.1.0/24", "192.0.2.0/24"]);
+        assert_eq!(fit(&nets, 2), (v4(&["10.0.0.0/23", "192.0.2.0/24"]), 0));
+    }
+
+    #[test]
+    fn cheapest_merge_first() {
+        // 10.0.0.0/24 + 10.0.2.0/24 costs 512 addresses; 10.0.8.0/24 +
+        // 10.0.12.0/24 costs 1536
+        let nets = v4(&["10.0.0.0/24", "10.0.2.0/24", "10.0.8.0/24", "10.0.12.0/24"]);
+        assert_eq!(
+            fit(&nets, 3),
+            (v4(&["10.0.0.0/22", "10.0.8.0/24", "10.0.12.0/24"]), 512)
+        );
+        assert_eq!(fit(&nets, 2), (v4(&["10.0.0.0/22", "10.0.8.0/21"]), 2048));
+        assert_eq!(fit(&nets, 1), (v4(&["10.0.0.0/20"]), 4096 - 1024));
+    }
+
+    #[test]
+    fn supernet_absorbs_neighbours() {
+        // Merging the two /32s gives 10.0.0.0/30, which also holds the /31...
+        // and then completes 10.0.0.0/29 with 10.0.0.4/30
+        let nets = v4(&["10.0.0.0/32", "10.0.0.2/31", "10.0.0.4/30", "10.0.1.0/24"]);
+        assert_eq!(fit(&nets, 2), (v4(&["10.0.0.0/29", "10.0.1.0/24"]), 1));
+    }
+
+    #[test]
+    fn whole_address_space() {
+        let nets: Vec<Ipv6Net> = ["::/1", "ffff::/16"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let (out, added) = fit(&nets, 1);
+        assert_eq!(out, vec!["::/0".parse::<Ipv6Net>().unwrap()]);
+        assert_eq!(added, (1u128 << 127) - (1u128 << 112));
+    }
+}
@@ -0,0 +1,150 @@
+//! Lossy aggregation: collapsing prefixes into a covering supernet once they
+//! fill enough of it.
+
+use crate::ranges::{self, Prefix, Range};
+
+/// Addresses of `block` covered by `ranges`, which must be sorted and
+/// disjoint.
+fn covered(ranges: &[Range], (first, last): Range) -> u128 {
+    let start = ranges.partition_point(|r| r.1 < first);
+    ranges[start..]
+        .iter()
+        .take_while(|r| r.0 <= last)
+        .map(|r| r.1.min(last) - r.0.max(first) + 1)
+        .sum()
+}
+
+struct Search<'a> {
+    ranges: &'a [Range],
+    min_coverage: f64,
+    /// Supernets collapsed into, with the addresses of each that were covered
+    found: Vec<(Range, u8, u128)>,
+}
+
+impl Search<'_> {
+    /// Find the largest blocks within `range`, a prefix of length `len`, that
+    /// are covered enough to collapse.
+    fn search<P: Prefix>(&mut self, range: Range, len: u8) {
+        // Fully covered blocks are already in the output, and might be all
+        // of ::/0, which is too large to count
+        let start = self.ranges.partition_point(|r| r.1 < range.0);
+        if let Some(r) = self.ranges.get(start) {
+            if r.0 <= range.0 && r.1 >= range.1 {
+                return;
+            }
+        }
+        let covered = covered(self.ranges, range);
+        if covered == 0 {
+            return;
+        }
+        if covered as f64 / ((range.1 - range.0) as f64 + 1.0) >= self.min_coverage {
+            self.found.push((range, len, covered));
+            return;
+        }
+        let half = (range.1 - range.0) / 2;
+        self.search::<P>((range.0, range.0 + half), len + 1);
+        self.search::<P>((range.0 + half + 1, range.1), len + 1);
+    }
+}
+
+/// A supernet that was collapsed into, with the addresses it added.
+pub(crate) struct Collapsed<P> {
+    pub(crate) supernet: P,
+    pub(crate) covered: u128,
+    pub(crate) gaps: Vec<P>,
+}
+
+/// Replace prefixes in `nets` with their covering supernet, of length at least
+/// `min_len`, wherever they cover at least `min_coverage` (0 to 1) of it.
+///
+/// Returns the aggregated result and the supernets that were collapsed into.
+pub(crate) fn collapse<P: Prefix>(
+    nets: &[P],
+    min_coverage: f64,
+    min_len: u8,
+) -> (Vec<P>, Vec<Collapsed<P>>) {
+    let ranges = ranges::to_ranges(nets);
+    let min_len = min_len.min(P::BITS);
+    let mut search = Search {
+        ranges: &ranges,
+        min_coverage,
+        found: Vec::new(),
+    };
+    // Only blocks holding the start or end of a range can be partly covered
+    let mut last_block = None;
+    for &(first, last) in &ranges {
+        for addr in [first, last] {
+            let block = ranges::block::<P>(addr, min_len);
+            if last_block != Some(block) {
+                search.search::<P>(block, min_len);
+                last_block = Some(block);
+            }
+        }
+    }
+
+    let found = search.found;
+    let mut all = ranges.clone();
+    all.extend(found.iter().map(|(range, _, _)| *range));
+    let nets: Vec<P> = ranges::to_prefixes(&ranges::merge(all));
+    let collapsed = found
+        .into_iter()
+        .map(|(range, len, covered)| Collapsed {
+            supernet: P::from_bits(range.0, len),
+            covered,
+            gaps: ranges::to_prefixes(&ranges::difference(&[range], &ranges)),
+        })
+        .collect();
+    (nets, collapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipnet::{Ipv4Net, Ipv6Net};
+
+    fn v4(nets: &[&str]) -> Vec<Ipv4Net> {
+        nets.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn collapse_scattered_hosts() {
+        let nets = v4(&[
+            "10.0.0.0/26",
+            "10.0.0.64/26",
+            "10.0.0.128/26",
+            "10.0.1.1/32",
+        ]);
+        let (out, collapsed) = collapse(&nets, 0.75, 20);
+        assert_eq!(out, v4(&["10.0.0.0/24", "10.0.1.1/32"]));
+        assert_eq!(collapsed.len(), 1);
+        assert_eq!(collapsed[0].supernet, "10.0.0.0/24".parse().unwrap());
+        assert_eq!(collapsed[0].covered, 192);
+        assert_eq!(collapsed[0].gaps, v4(&["10.0.0.192/26"]));
+    }
+
+    #[test]
+    fn respects_min_len() {
+        let nets = v4(&["10.0.0.0/25", "10.0.1.0/24"]);
+        assert_eq!(collapse(&nets, 0.75, 24).0, nets);
+        assert_eq!(collapse(&nets, 0.75, 23).0, v4(&["10.0.0.0/23"]));
+    }
+
+    #[test]
+    fn below_threshold_unchanged() {
+        let nets = v4(&["10.0.0.0/26", "10.0.0.128/26"]);
+        let (out, collapsed) = collapse(&nets, 0.75, 0);
+        assert_eq!(out, nets);
+        assert!(collapsed.is_empty());
+    }
+
+    #[test]
+    fn whole_address_space() {
+        let nets: Vec<Ipv6Net> = ["::/1", "8000::/2"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let (out, collapsed) = collapse(&nets, 0.75, 0);
+        assert_eq!(out, vec!["::/0".parse::<Ipv6Net>().unwrap()]);
+        assert_eq!(collapsed[0].gaps, vec!["c000::/2".parse().unwrap()]);
+    }
+}
@@ -0,0 +1,273 @@
+//! Forwarding table compression with the ORTC (Optimal Routing Table
+//! Constructor) algorithm of Draves, King, Venkatachary and Zill.
+//!
+//! Unlike aggregation, routes with different next-hops can be combined, by
+//! routing a supernet to the most common next-hop and overriding the rest
+//! with more-specifics.
+
+use std::{collections::HashMap, fmt::Display};
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+
+use crate::{ranges::Prefix, IpOrNet};
+
+/// Next-hop id of addresses without a route
+const UNROUTED: u32 = 0;
+
+/// A forwarding table of prefixes and their next-hops.
+///
+/// Next-hops are opaque strings, so they may be addresses, interface names or
+/// anything else, and are compared exactly.
+#[derive(Clone, Debug, Default)]
+pub struct RouteTable {
+    v4: Vec<(Ipv4Net, u32)>,
+    v6: Vec<(Ipv6Net, u32)>,
+    /// Next-hop names, indexed by id - 1, as id 0 is [`UNROUTED`]
+    next_hops: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl RouteTable {
+    pub fn new() -> RouteTable {
+        RouteTable::default()
+    }
+    /// Add a route. Where the same prefix is added more than once, the last
+    /// next-hop given is used.
+    pub fn add(&mut self, prefix: IpOrNet, next_hop: &str) {
+        let id = match self.ids.get(next_hop) {
+            Some(id) => *id,
+            None => {
+                self.next_hops.push(next_hop.to_owned());
+                let id = self.next_hops.len() as u32;
+                self.ids.insert(next_hop.to_owned(), id);
+                id
+            }
+        };
+        match prefix.trunc().net() {
+            IpNet::V4(net) => self.v4.push((net, id)),
+            IpNet::V6(net) => self.v6.push((net, id)),
+        }
+    }
+    /// Number of routes currently held, across both address families.
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+    /// Replace the routes with the smallest table that forwards every address
+    /// to the same next-hop under longest-prefix-match, and leaves the same
+    /// addresses unrouted.
+    pub fn compress(&mut self) {
+        self.v4 = compress(&self.v4);
+        self.v6 = compress(&self.v6);
+    }
+    /// Routes as `(prefix, next-hop)`, IPv4 first, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (IpNet, &str)> {
+        let v4 = self.v4.iter().map(|(net, id)| (IpNet::V4(*net), *id));
+        let v6 = self.v6.iter().map(|(net, id)| (IpNet::V6(*net), *id));
+        v4.chain(v6)
+            .map(|(net, id)| (net, self.next_hops[id as usize - 1].as_str()))
+    }
+}
+
+/// One `prefix next-hop` line per route
+impl Display for RouteTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (net, next_hop) in self.iter() {
+            writeln!(f, "{} {}", net, next_hop)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    children: Option<[usize; 2]>,
+    /// Next-hop of a route for exactly this prefix
+    route: Option<u32>,
+    /// Candidate next-hops from the second pass, sorted
+    candidates: Vec<u32>,
+}
+
+/// A binary trie over the address bits, built in an arena.
+struct Trie {
+    nodes: Vec<Node>,
+}
+
+impl Trie {
+    fn insert<P: Prefix>(&mut self, net: &P, next_hop: u32) {
+        let mut node = 0;
+        for depth in 0..net.len() {
+            let bit = (net.first() >> (P::BITS - 1 - depth)) as usize & 1;
+            node = self.children(node)[bit];
+        }
+        self.nodes[node].route = Some(next_hop);
+    }
+
+    /// Children of `node`, creating them if necessary
+    fn children(&mut self, node: usize) -> [usize; 2] {
+        if let Some(children) = self.nodes[node].children {
+            return children;
+        }
+        let children = [self.nodes.len(), self.nodes.len() + 1];
+        self.nodes.push(Node::default());
+        self.nodes.push(Node::default());
+        self.nodes[node].children = Some(children);
+        children
+    }
+
+    /// First and second passes: give every leaf the next-hop it inherits, and
+    /// work out the candidate next-hops of each node from its children.
+    ///
+    /// Nodes covering any unrouted address are only allowed to be unrouted, so
+    /// no route is ever chosen that would send those addresses somewhere.
+    fn candidates(&mut self, node: usize, inherited: u32) {
+        let inherited = self.nodes[node].route.unwrap_or(inherited);
+        let candidates = match self.nodes[node].children {
+            None => vec![inherited],
+            Some([left, right]) => {
+                self.candidates(left, inherited);
+                self.candidates(right, inherited);
+                let (a, b) = (&self.nodes[left].candidates, &self.nodes[right].candidates);
+                if a.contains(&UNROUTED) || b.contains(&UNROUTED) {
+                    vec![UNROUTED]
+                } else {
+                    let both: Vec<u32> = a.iter().filter(|id| b.contains(id)).copied().collect();
+                    match both.is_empty() {
+                        true => merge_sorted(a, b),
+                        false => both,
+                    }
+                }
+            }
+        };
+        self.nodes[node].candidates = candidates;
+    }
+
+    /// Third pass: choose each node's next-hop, emitting a route wherever it
+    /// differs from the one inherited.
+    fn select<P: Prefix>(
+        &self,
+        node: usize,
+        first: u128,
+        len: u8,
+        inherited: u32,
+        out: &mut Vec<(P, u32)>,
+    ) {
+        let candidates = &self.nodes[node].candidates;
+        let next_hop = match candidates.contains(&inherited) {
+            true => inherited,
+            false => {
+                out.push((P::from_bits(first, len), candidates[0]));
+                candidates[0]
+            }
+        };
+        if let Some([left, right]) = self.nodes[node].children {
+            let half = 1u128 << (P::BITS - len - 1);
+            self.select(left, first, len + 1, next_hop, out);
+            self.select(right, first + half, len + 1, next_hop, out);
+        }
+    }
+}
+
+fn merge_sorted(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out: Vec<u32> = a.iter().chain(b).copied().collect();
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+fn compress<P: Prefix>(routes: &[(P, u32)]) -> Vec<(P, u32)> {
+    if routes.is_empty() {
+        return Vec::new();
+    }
+    let mut trie = Trie {
+        nodes: vec![Node::default()],
+    };
+    for (net, next_hop) in routes {
+        trie.insert(net, *next_hop);
+    }
+    trie.candidates(0, UNROUTED);
+    let mut out = Vec::new();
+    trie.select(0, 0, 0, UNROUTED, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(routes: &[(&str, &str)]) -> RouteTable {
+        let mut table = RouteTable::new();
+        for (net, next_hop) in routes {
+            table.add(net.parse().unwrap(), next_hop);
+        }
+        table
+    }
+
+    #[test]
+    fn same_next_hop_aggregates() {
+        let mut routes = table(&[("10.0.0.0/24", "a"), ("10.0.1.0/24", "a")]);
+        routes.compress();
+        assert_eq!(routes.to_string(), "10.0.0.0/23 a\n");
+    }
+
+    #[test]
+    fn override_with_more_specific() {
+        let mut routes = table(&[
+            ("10.0.0.0/24", "a"),
+            ("10.0.1.0/25", "a"),
+            ("10.0.1.128/25", "b"),
+        ]);
+        routes.compress();
+        assert_eq!(routes.to_string(), "10.0.0.0/23 a\n10.0.1.128/25 b\n");
+    }
+
+    #[test]
+    fn most_common_next_hop_wins() {
+        let mut routes = table(&[
+            ("10.0.0.0/26", "b"),
+            ("10.0.0.64/26", "a"),
+            ("10.0.0.128/26", "a"),
+            ("10.0.0.192/26", "a"),
+        ]);
+        routes.compress();
+        assert_eq!(routes.to_string(), "10.0.0.0/24 a\n10.0.0.0/26 b\n");
+    }
+
+    #[test]
+    fn redundant_more_specific_removed() {
+        let mut routes = table(&[
+            ("10.0.0.0/8", "a"),
+            ("10.1.0.0/16", "a"),
+            ("10.2.0.0/16", "b"),
+        ]);
+        routes.compress();
+        assert_eq!(routes.to_string(), "10.0.0.0/8 a\n10.2.0.0/16 b\n");
+    }
+
+    #[test]
+    fn unrouted_space_stays_unrouted() {
+        // Routing 10.0.0.0/22 to a would be smaller, but would route 10.0.3.0/24
+        let mut routes = table(&[
+            ("10.0.0.0/24", "a"),
+            ("10.0.1.0/24", "a"),
+            ("10.0.2.0/24", "a"),
+        ]);
+        routes.compress();
+        assert_eq!(routes.to_string(), "10.0.0.0/23 a\n10.0.2.0/24 a\n");
+    }
+
+    #[test]
+    fn default_route_and_families() {
+        let mut routes = table(&[
+            ("0.0.0.0/0", "a"),
+            ("10.0.0.0/8", "b"),
+            ("10.0.0.0/9", "a"),
+            ("10.128.0.0/9", "a"),
+            ("2001:db8::/32", "c"),
+        ]);
+        routes.compress();
+        assert_eq!(routes.to_string(), "0.0.0.0/0 a\n2001:db8::/32 c\n");
+    }
+}
@@ -9,7 +9,13 @@ use std::{
 
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 
-#[derive(Default)]
+use crate::budget;
+use crate::coverage;
+use crate::prefixrange::PrefixRange;
+use crate::ranges::{self, Prefix};
+
+/// A set of IPv4 and IPv6 prefixes, kept separately per address family.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct IpBothRange {
     v4: Vec<Ipv4Net>,
     v6: Vec<Ipv6Net>,
@@ -25,6 +31,14 @@ impl IpBothRange {
             IpNet::V6(n) => self.v6.push(n),
         }
     }
+    /// Number of prefixes currently held, across both address families.
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+    /// Aggregate the held prefixes into their minimal representation.
     #[cfg(feature = "rayon")]
     pub fn simplify(&mut self) {
         (self.v4, self.v6) = join(
@@ -37,6 +51,178 @@ impl IpBothRange {
         self.v4 = Ipv4Net::aggregate(&self.v4);
         self.v6 = Ipv6Net::aggregate(&self.v6);
     }
+    /// Remove prefixes covered by another held prefix, and duplicates, without
+    /// merging adjacent prefixes into a new supernet.
+    #[cfg(feature = "rayon")]
+    pub fn remove_redundant(&mut self) {
+        (self.v4, self.v6) = join(|| remove_redundant(&self.v4), || remove_redundant(&self.v6));
+    }
+    #[cfg(not(feature = "rayon"))]
+    pub fn remove_redundant(&mut self) {
+        self.v4 = remove_redundant(&self.v4);
+        self.v6 = remove_redundant(&self.v6);
+    }
+
+    /// Addresses covered by `self` but not by `other`, aggregated.
+    ///
+    /// Neither input needs to be simplified first.
+    pub fn difference(&self, other: &IpBothRange) -> IpBothRange {
+        IpBothRange {
+            v4: difference(&self.v4, &other.v4),
+            v6: difference(&self.v6, &other.v6),
+        }
+    }
+
+    /// Addresses covered by both `self` and `other`, aggregated.
+    ///
+    /// Neither input needs to be simplified first.
+    pub fn intersection(&self, other: &IpBothRange) -> IpBothRange {
+        IpBothRange {
+            v4: intersection(&self.v4, &other.v4),
+            v6: intersection(&self.v6, &other.v6),
+        }
+    }
+
+    /// All addresses not covered by `self`, aggregated.
+    ///
+    /// To invert within a smaller universe use `universe.difference(&self)`.
+    pub fn complement(&self) -> IpBothRange {
+        IpBothRange {
+            v4: difference(&[Ipv4Net::default()], &self.v4),
+            v6: difference(&[Ipv6Net::default()], &self.v6),
+        }
+    }
+
+    /// Number of distinct IPv4 addresses covered.
+    pub fn v4_address_count(&self) -> AddressCount {
+        address_count(&self.v4)
+    }
+
+    /// Number of distinct IPv6 addresses covered.
+    pub fn v6_address_count(&self) -> AddressCount {
+        address_count(&self.v6)
+    }
+
+    /// Split any prefix shorter than `min_len` into its subnets of that
+    /// length, for each address family.
+    pub fn split_shorter_than(&mut self, min_len: &PrefixlenPair) {
+        self.v4 = split_shorter_than(&self.v4, min_len.v4);
+        self.v6 = split_shorter_than(&self.v6, min_len.v6);
+    }
+
+    /// Prefix-list entries with length ranges matching exactly the held
+    /// prefixes, rather than the addresses they cover, IPv4 first.
+    ///
+    /// Prefixes are used as given, so this should be called before any
+    /// simplification.
+    pub fn prefix_ranges(&self) -> Vec<PrefixRange> {
+        let exact: Vec<PrefixRange> = self.into_iter().map(PrefixRange::from).collect();
+        PrefixRange::compress(&exact)
+    }
+
+    /// Each IPv4 prefix split into all of its subnets of length `len`, without
+    /// collecting them. Prefixes already at least as long are kept as they are.
+    pub fn v4_split(&self, len: u8) -> impl Iterator<Item = Ipv4Net> + '_ {
+        let len = len.min(32);
+        (self.v4.iter()).flat_map(move |net| net.subnets(len.max(net.prefix_len())).unwrap())
+    }
+
+    /// Each IPv6 prefix split into all of its subnets of length `len`, without
+    /// collecting them. Prefixes already at least as long are kept as they are.
+    pub fn v6_split(&self, len: u8) -> impl Iterator<Item = Ipv6Net> + '_ {
+        let len = len.min(128);
+        (self.v6.iter()).flat_map(move |net| net.subnets(len.max(net.prefix_len())).unwrap())
+    }
+
+    /// Number of prefixes [`v4_split`](Self::v4_split) and
+    /// [`v6_split`](Self::v6_split) would produce together, or `None` if it
+    /// doesn't fit in a `u128`.
+    pub fn split_count(&self, len: &PrefixlenPair) -> Option<u128> {
+        let v4 = self.v4.iter().map(|net| split_count(net, len.v4));
+        let v6 = self.v6.iter().map(|net| split_count(net, len.v6));
+        v4.chain(v6)
+            .try_fold(0u128, |total, count| total.checked_add(count?))
+    }
+
+    /// Aggregate, then merge prefixes into covering supernets until each
+    /// address family has at most the number of entries given in `limit`.
+    ///
+    /// Merges adding the fewest addresses are made first. Returns the number
+    /// of addresses added to each of IPv4 and IPv6.
+    #[cfg(feature = "rayon")]
+    pub fn fit_entries(&mut self, limit: &EntryLimit) -> (AddressCount, AddressCount) {
+        let ((v4, v4_added), (v6, v6_added)) = join(
+            || budget::fit(&self.v4, limit.v4),
+            || budget::fit(&self.v6, limit.v6),
+        );
+        (self.v4, self.v6) = (v4, v6);
+        (AddressCount(Some(v4_added)), AddressCount(Some(v6_added)))
+    }
+    #[cfg(not(feature = "rayon"))]
+    pub fn fit_entries(&mut self, limit: &EntryLimit) -> (AddressCount, AddressCount) {
+        let (v4, v4_added) = budget::fit(&self.v4, limit.v4);
+        let (v6, v6_added) = budget::fit(&self.v6, limit.v6);
+        (self.v4, self.v6) = (v4, v6);
+        (AddressCount(Some(v4_added)), AddressCount(Some(v6_added)))
+    }
+
+    /// Aggregate, then replace prefixes with their covering supernet wherever
+    /// they cover at least `min_coverage` (0 to 1) of it. Supernets are no
+    /// shorter than `min_len` for each address family.
+    ///
+    /// Returns the supernets that were introduced, IPv4 first.
+    pub fn approximate(
+        &mut self,
+        min_coverage: f64,
+        min_len: &PrefixlenPair,
+    ) -> Vec<Approximation> {
+        let (v4, v4_collapsed) = coverage::collapse(&self.v4, min_coverage, min_len.v4);
+        let (v6, v6_collapsed) = coverage::collapse(&self.v6, min_coverage, min_len.v6);
+        (self.v4, self.v6) = (v4, v6);
+        (v4_collapsed.into_iter().map(Approximation::from))
+            .chain(v6_collapsed.into_iter().map(Approximation::from))
+            .collect()
+    }
+
+    /// Prefixes to remove from and add to `self` to turn it into `new`,
+    /// comparing whole prefixes rather than the address space they cover.
+    ///
+    /// Returns `(removed, added)`. This is what is needed to update a set of
+    /// prefixes held elsewhere, such as a firewall set, in place.
+    pub fn changes_to(&self, new: &IpBothRange) -> (IpBothRange, IpBothRange) {
+        let (v4_removed, v4_added) = changes(&self.v4, &new.v4);
+        let (v6_removed, v6_added) = changes(&self.v6, &new.v6);
+        (
+            IpBothRange {
+                v4: v4_removed,
+                v6: v6_removed,
+            },
+            IpBothRange {
+                v4: v4_added,
+                v6: v6_added,
+            },
+        )
+    }
+
+    /// Maximal runs of contiguous addresses, IPv4 first, in ascending order.
+    ///
+    /// Adjacent and overlapping prefixes are merged even where they could not
+    /// be combined into a single prefix.
+    pub fn ranges(&self) -> impl Iterator<Item = IpRange> {
+        let v4 = ranges::to_ranges(&self.v4)
+            .into_iter()
+            .map(|(first, last)| IpRange {
+                first: Ipv4Addr::from(first as u32).into(),
+                last: Ipv4Addr::from(last as u32).into(),
+            });
+        let v6 = ranges::to_ranges(&self.v6)
+            .into_iter()
+            .map(|(first, last)| IpRange {
+                first: Ipv6Addr::from(first).into(),
+                last: Ipv6Addr::from(last).into(),
+            });
+        v4.chain(v6)
+    }
 
     pub fn v4_iter(&self) -> impl Iterator<Item = &Ipv4Net> {
         self.v4.iter()
@@ -47,6 +233,183 @@ impl IpBothRange {
     }
 }
 
+fn difference<P: Prefix>(a: &[P], b: &[P]) -> Vec<P> {
+    ranges::to_prefixes(&ranges::difference(
+        &ranges::to_ranges(a),
+        &ranges::to_ranges(b),
+    ))
+}
+
+fn intersection<P: Prefix>(a: &[P], b: &[P]) -> Vec<P> {
+    ranges::to_prefixes(&ranges::intersection(
+        &ranges::to_ranges(a),
+        &ranges::to_ranges(b),
+    ))
+}
+
+fn remove_redundant<P: Prefix>(nets: &[P]) -> Vec<P> {
+    // Sorting by first address, then length, puts each prefix after any that
+    // contain it
+    let mut sorted: Vec<(u128, u8)> = nets.iter().map(|n| (n.first(), n.len())).collect();
+    sorted.sort_unstable();
+    let mut out: Vec<P> = Vec::with_capacity(sorted.len());
+    for (first, len) in sorted {
+        let net = P::from_bits(first, len);
+        match out.last() {
+            Some(prev) if net.last() <= prev.last() => (),
+            _ => out.push(net),
+        }
+    }
+    out
+}
+
+fn split_shorter_than<P: Prefix>(nets: &[P], min_len: u8) -> Vec<P> {
+    let min_len = min_len.min(P::BITS);
+    let mut out = Vec::with_capacity(nets.len());
+    for net in nets {
+        if net.len() >= min_len {
+            out.push(*net);
+            continue;
+        }
+        let (first, last) = (net.first(), net.last());
+        let step = 1u128 << (P::BITS - min_len);
+        let mut addr = first;
+        loop {
+            out.push(P::from_bits(addr, min_len));
+            if last - addr < step {
+                break;
+            }
+            addr += step;
+        }
+    }
+    out
+}
+
+fn split_count<P: Prefix>(net: &P, len: u8) -> Option<u128> {
+    let len = len.min(P::BITS);
+    1u128.checked_shl(len.saturating_sub(net.len()).into())
+}
+
+fn address_count<P: Prefix>(nets: &[P]) -> AddressCount {
+    // Ranges are disjoint so the total can only reach 2^128 if there is just
+    // the one range covering everything
+    let total = ranges::to_ranges(nets)
+        .iter()
+        .map(|(first, last)| (last - first).checked_add(1))
+        .sum::<Option<u128>>();
+    AddressCount(total)
+}
+
+/// A supernet introduced by [`IpBothRange::approximate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Approximation {
+    pub supernet: IpNet,
+    /// Addresses of the supernet that were in the input
+    pub covered: AddressCount,
+    /// Parts of the supernet that were not in the input
+    pub gaps: Vec<IpNet>,
+}
+
+impl Approximation {
+    /// Fraction of the supernet that was in the input.
+    pub fn coverage(&self) -> f64 {
+        let host_bits = self.supernet.max_prefix_len() - self.supernet.prefix_len();
+        self.covered.as_f64() / AddressCount::from_host_bits(host_bits).as_f64()
+    }
+}
+
+impl<P: Prefix + Into<IpNet>> From<coverage::Collapsed<P>> for Approximation {
+    fn from(collapsed: coverage::Collapsed<P>) -> Self {
+        Approximation {
+            supernet: collapsed.supernet.into(),
+            covered: AddressCount(Some(collapsed.covered)),
+            gaps: collapsed.gaps.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// A number of addresses.
+///
+/// All of IPv6 is 2^128 addresses, one more than fits in a `u128`, so that
+/// case is tracked separately.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AddressCount(Option<u128>);
+
+impl AddressCount {
+    /// The number of addresses in a prefix with `host_bits` host bits.
+    pub fn from_host_bits(host_bits: u8) -> Self {
+        AddressCount(1u128.checked_shl(host_bits.into()))
+    }
+    /// The count, or `None` if it is 2^128.
+    pub fn get(&self) -> Option<u128> {
+        self.0
+    }
+    /// The count as a float, for ratios and scaled units.
+    pub fn as_f64(&self) -> f64 {
+        match self.0 {
+            Some(n) => n as f64,
+            None => 2f64.powi(128),
+        }
+    }
+}
+
+impl Display for AddressCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(n) => n.fmt(f),
+            None => f.write_str("340282366920938463463374607431768211456"),
+        }
+    }
+}
+
+/// Returns `(only in old, only in new)`
+fn changes<P: Prefix>(old: &[P], new: &[P]) -> (Vec<P>, Vec<P>) {
+    let mut old = old.to_vec();
+    let mut new = new.to_vec();
+    old.sort_unstable();
+    old.dedup();
+    new.sort_unstable();
+    new.dedup();
+
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        match old[i].cmp(&new[j]) {
+            std::cmp::Ordering::Less => {
+                removed.push(old[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                added.push(new[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    removed.extend_from_slice(&old[i..]);
+    added.extend_from_slice(&new[j..]);
+    (removed, added)
+}
+
+impl FromIterator<IpOrNet> for IpBothRange {
+    fn from_iter<T: IntoIterator<Item = IpOrNet>>(iter: T) -> Self {
+        let mut range = IpBothRange::new();
+        range.extend(iter);
+        range
+    }
+}
+
+impl Extend<IpOrNet> for IpBothRange {
+    fn extend<T: IntoIterator<Item = IpOrNet>>(&mut self, iter: T) {
+        for net in iter {
+            self.add(net);
+        }
+    }
+}
+
 impl Display for IpBothRange {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for ip in self {
@@ -96,18 +459,19 @@ impl<'a> IntoIterator for &'a IpBothRange {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// A single IP network, parsed from a bare address, a CIDR prefix, or an IPv4
+/// address with a netmask or wildcard mask.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct IpOrNet(IpNet);
 
 #[derive(Debug, Clone)]
 pub struct NetParseError {
-    #[allow(dead_code)]
-    msg: &'static str,
+    pub(crate) msg: &'static str,
 }
 
 impl Display for NetParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("Unable to parse address")
+        f.write_str(self.msg)
     }
 }
 
@@ -182,6 +546,25 @@ impl IpOrNet {
     pub fn has_host_bits(&self) -> bool {
         self.0.addr() != self.0.network()
     }
+    pub fn net(&self) -> IpNet {
+        self.0
+    }
+    /// The same prefix with any host bits cleared.
+    pub fn trunc(&self) -> IpOrNet {
+        IpOrNet(self.0.trunc())
+    }
+    /// The covering prefix of the length given in `len` for this address
+    /// family, or the prefix itself, truncated, if it is already as short.
+    pub fn widen(&self, len: &PrefixlenPair) -> IpOrNet {
+        let len = match self.0 {
+            IpNet::V4(_) => len.v4.min(32),
+            IpNet::V6(_) => len.v6,
+        };
+        match IpNet::new(self.addr(), len) {
+            Ok(net) if len < self.prefix_len() => IpOrNet(net.trunc()),
+            _ => self.trunc(),
+        }
+    }
 }
 
 impl FromStr for IpOrNet {
@@ -207,6 +590,12 @@ impl From<IpNet> for IpOrNet {
     }
 }
 
+impl From<IpOrNet> for IpNet {
+    fn from(net: IpOrNet) -> Self {
+        net.0
+    }
+}
+
 impl From<IpAddr> for IpOrNet {
     fn from(addr: IpAddr) -> Self {
         IpOrNet(addr.into())
@@ -237,6 +626,71 @@ impl From<Ipv6Addr> for IpOrNet {
     }
 }
 
+/// An inclusive range of addresses within one address family, written as
+/// `first-last`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpRange {
+    first: IpAddr,
+    last: IpAddr,
+}
+
+impl IpRange {
+    pub fn new(first: IpAddr, last: IpAddr) -> Result<Self, NetParseError> {
+        if first.is_ipv4() != last.is_ipv4() {
+            return Err(NetParseError {
+                msg: "Range start and end are in different address families",
+            });
+        }
+        if first > last {
+            return Err(NetParseError {
+                msg: "Range start is after range end",
+            });
+        }
+        Ok(IpRange { first, last })
+    }
+    pub fn first(&self) -> IpAddr {
+        self.first
+    }
+    pub fn last(&self) -> IpAddr {
+        self.last
+    }
+    /// The minimal list of prefixes covering exactly this range.
+    pub fn prefixes(&self) -> Vec<IpOrNet> {
+        match (self.first, self.last) {
+            (IpAddr::V4(first), IpAddr::V4(last)) => {
+                let mut out: Vec<Ipv4Net> = Vec::new();
+                ranges::push_range(&mut out, (u32::from(first).into(), u32::from(last).into()));
+                out.into_iter().map(IpOrNet::from).collect()
+            }
+            (IpAddr::V6(first), IpAddr::V6(last)) => {
+                let mut out: Vec<Ipv6Net> = Vec::new();
+                ranges::push_range(&mut out, (first.into(), last.into()));
+                out.into_iter().map(IpOrNet::from).collect()
+            }
+            _ => unreachable!("IpRange families are checked on construction"),
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('-') {
+            Some((first, last)) => Ok(IpRange::new(first.trim().parse()?, last.trim().parse()?)?),
+            None => Err(Box::new(NetParseError {
+                msg: "Range must be in first-last form",
+            })),
+        }
+    }
+}
+
+impl Display for IpRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.first, self.last)
+    }
+}
+
+/// A pair of prefix lengths, one for each address family.
 #[derive(Clone, Debug)]
 pub struct PrefixlenPair {
     pub v4: u8,
@@ -349,6 +803,57 @@ impl FromStr for PrefixlenPair {
     }
 }
 
+/// A maximum number of prefixes for each address family.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryLimit {
+    pub v4: usize,
+    pub v6: usize,
+}
+
+impl Display for EntryLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{},{}", self.v4, self.v6))
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseEntryLimitError {
+    msg: &'static str,
+}
+
+impl Display for ParseEntryLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.msg)
+    }
+}
+
+impl std::error::Error for ParseEntryLimitError {}
+
+impl FromStr for EntryLimit {
+    type Err = ParseEntryLimitError;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parse = |s: &str| match usize::from_str(s) {
+            Ok(0) => Err(ParseEntryLimitError {
+                msg: "Limit must be at least 1",
+            }),
+            Ok(n) => Ok(n),
+            Err(_) => Err(ParseEntryLimitError {
+                msg: "Unable to parse integer",
+            }),
+        };
+        match s.split_once(',') {
+            Some((v4, v6)) => Ok(EntryLimit {
+                v4: parse(v4)?,
+                v6: parse(v6)?,
+            }),
+            None => {
+                let max = parse(s)?;
+                Ok(EntryLimit { v4: max, v6: max })
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::panic;
@@ -444,6 +949,239 @@ mod tests {
     fn reject_v6_invalid_pfxlen() {
         let _net: IpOrNet = "2001:db8::32ab:0/129".parse().unwrap();
     }
+    fn both(nets: &[&str]) -> IpBothRange {
+        nets.iter().map(|s| s.parse::<IpOrNet>().unwrap()).collect()
+    }
+    #[test]
+    fn difference_splits_supernet() {
+        let result = both(&["10.0.0.0/16"]).difference(&both(&["10.0.4.0/24"]));
+        assert_eq!(
+            result,
+            both(&[
+                "10.0.0.0/22",
+                "10.0.5.0/24",
+                "10.0.6.0/23",
+                "10.0.8.0/21",
+                "10.0.16.0/20",
+                "10.0.32.0/19",
+                "10.0.64.0/18",
+                "10.0.128.0/17",
+            ])
+        );
+    }
+    #[test]
+    fn difference_per_family() {
+        let result =
+            both(&["192.0.2.0/24", "2001:db8::/32"]).difference(&both(&["192.0.2.0/25", "::/0"]));
+        assert_eq!(result, both(&["192.0.2.128/25"]));
+    }
+    #[test]
+    fn difference_unsimplified() {
+        let result = both(&["192.0.2.0/25", "192.0.2.128/25", "192.0.2.0/24"])
+            .difference(&both(&["198.51.100.0/24"]));
+        assert_eq!(result, both(&["192.0.2.0/24"]));
+    }
+    #[test]
+    fn intersection_partial_overlap() {
+        let result =
+            both(&["10.0.0.0/23", "192.0.2.0/24", "2001:db8::/32"]).intersection(&both(&[
+                "10.0.1.0/24",
+                "10.0.2.0/24",
+                "192.0.2.128/26",
+                "::/0",
+            ]));
+        assert_eq!(
+            result,
+            both(&["10.0.1.0/24", "192.0.2.128/26", "2001:db8::/32"])
+        );
+    }
+    #[test]
+    fn intersection_disjoint() {
+        let result = both(&["10.0.0.0/8"]).intersection(&both(&["2001:db8::/32"]));
+        assert!(result.is_empty());
+    }
+    #[test]
+    fn complement_full() {
+        assert_eq!(
+            both(&["0.0.0.0/1", "::/0"]).complement(),
+            both(&["128.0.0.0/1"])
+        );
+        assert_eq!(
+            IpBothRange::new().complement(),
+            both(&["0.0.0.0/0", "::/0"])
+        );
+    }
+    #[test]
+    fn remove_redundant_keeps_siblings() {
+        let mut set = both(&[
+            "10.0.1.0/24",
+            "10.0.0.0/24",
+            "10.0.0.128/25",
+            "10.0.1.0/24",
+            "192.0.2.0/24",
+            "192.0.0.0/16",
+            "2001:db8::/48",
+            "2001:db8:1::/48",
+        ]);
+        set.remove_redundant();
+        assert_eq!(
+            set,
+            both(&[
+                "10.0.0.0/24",
+                "10.0.1.0/24",
+                "192.0.0.0/16",
+                "2001:db8::/48",
+                "2001:db8:1::/48",
+            ])
+        );
+    }
+    #[test]
+    fn widen_to_prefixlen() {
+        let len = PrefixlenPair { v4: 24, v6: 48 };
+        let widened = |s: &str| s.parse::<IpOrNet>().unwrap().widen(&len).to_string();
+        assert_eq!(widened("192.0.2.123"), "192.0.2.0/24");
+        assert_eq!(widened("192.0.2.64/26"), "192.0.2.0/24");
+        assert_eq!(widened("10.0.0.0/8"), "10.0.0.0/8");
+        assert_eq!(widened("2001:db8:0:1::/64"), "2001:db8::/48");
+        assert_eq!(widened("2001:db8::/32"), "2001:db8::/32");
+    }
+    #[test]
+    fn split_to_prefixlen() {
+        let set = both(&["10.0.0.0/23", "10.1.0.0/25", "2001:db8::/47"]);
+        let v4: Vec<String> = set.v4_split(24).map(|n| n.to_string()).collect();
+        assert_eq!(v4, ["10.0.0.0/24", "10.0.1.0/24", "10.1.0.0/25"]);
+        let v6: Vec<String> = set.v6_split(48).map(|n| n.to_string()).collect();
+        assert_eq!(v6, ["2001:db8::/48", "2001:db8:1::/48"]);
+        let len = PrefixlenPair { v4: 24, v6: 48 };
+        assert_eq!(set.split_count(&len), Some(5));
+        let len = PrefixlenPair { v4: 32, v6: 128 };
+        assert_eq!(both(&["::/0"]).split_count(&len), None);
+        assert_eq!(both(&["::/1"]).split_count(&len), Some(1 << 127));
+    }
+    #[test]
+    fn split_shorter_than_floor() {
+        let mut set = both(&["10.0.0.0/22", "10.1.0.0/25", "2001:db8::/47"]);
+        set.split_shorter_than(&PrefixlenPair { v4: 24, v6: 48 });
+        assert_eq!(
+            set,
+            both(&[
+                "10.0.0.0/24",
+                "10.0.1.0/24",
+                "10.0.2.0/24",
+                "10.0.3.0/24",
+                "10.1.0.0/25",
+                "2001:db8::/48",
+                "2001:db8:1::/48",
+            ])
+        );
+        let mut all = both(&["::/0"]);
+        all.split_shorter_than(&PrefixlenPair { v4: 1, v6: 1 });
+        assert_eq!(all, both(&["::/1", "8000::/1"]));
+    }
+    #[test]
+    fn parse_range_v4() {
+        let range: IpRange = "192.0.2.1-192.0.2.6".parse().unwrap();
+        assert_eq!(
+            range.prefixes(),
+            [
+                "192.0.2.1/32",
+                "192.0.2.2/31",
+                "192.0.2.4/31",
+                "192.0.2.6/32"
+            ]
+            .iter()
+            .map(|s| s.parse::<IpOrNet>().unwrap())
+            .collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn parse_range_v6() {
+        let range: IpRange = "2001:db8::-2001:db8::ffff:ffff:ffff:ffff".parse().unwrap();
+        assert_eq!(range.prefixes(), vec!["2001:db8::/64".parse().unwrap()]);
+        let range: IpRange = "::-ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff"
+            .parse()
+            .unwrap();
+        assert_eq!(range.prefixes(), vec![TEST_V6_ALLNET.into()]);
+    }
+    #[test]
+    fn parse_range_single() {
+        let range: IpRange = "198.51.100.123-198.51.100.123".parse().unwrap();
+        assert_eq!(range.prefixes(), vec![TEST_V4_ADDR.into()]);
+    }
+    #[test]
+    #[should_panic]
+    fn reject_range_reversed() {
+        let _range: IpRange = "192.0.2.6-192.0.2.1".parse().unwrap();
+    }
+    #[test]
+    #[should_panic]
+    fn reject_range_mixed_family() {
+        let _range: IpRange = "192.0.2.1-2001:db8::1".parse().unwrap();
+    }
+    #[test]
+    fn range_error_message() {
+        let err =
+            IpRange::new("192.0.2.6".parse().unwrap(), "192.0.2.1".parse().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Range start is after range end");
+    }
+    #[test]
+    fn ranges_merge_runs() {
+        let runs: Vec<String> = both(&[
+            "192.0.2.0/24",
+            "192.0.3.0/25",
+            "198.51.100.7/32",
+            "2001:db8::/127",
+            "2001:db8::2/128",
+        ])
+        .ranges()
+        .map(|r| r.to_string())
+        .collect();
+        assert_eq!(
+            runs,
+            [
+                "192.0.2.0-192.0.3.127",
+                "198.51.100.7-198.51.100.7",
+                "2001:db8::-2001:db8::2"
+            ]
+        );
+    }
+    #[test]
+    fn changes_compare_prefixes() {
+        let old = both(&[
+            "10.0.0.0/24",
+            "10.0.1.0/24",
+            "192.0.2.0/24",
+            "2001:db8::/32",
+        ]);
+        let new = both(&[
+            "10.0.0.0/23",
+            "192.0.2.0/24",
+            "2001:db8::/32",
+            "2001:db8:1::/48",
+        ]);
+        let (removed, added) = old.changes_to(&new);
+        assert_eq!(removed, both(&["10.0.0.0/24", "10.0.1.0/24"]));
+        assert_eq!(added, both(&["10.0.0.0/23", "2001:db8:1::/48"]));
+    }
+    #[test]
+    fn address_counts() {
+        let set = both(&[
+            "10.0.0.0/8",
+            "10.0.0.0/24",
+            "192.0.2.0/24",
+            "2001:db8::/127",
+        ]);
+        assert_eq!(set.v4_address_count().get(), Some(16777472));
+        assert_eq!(set.v6_address_count().get(), Some(2));
+        let all = both(&["0.0.0.0/0", "::/1", "8000::/1"]);
+        assert_eq!(all.v4_address_count().get(), Some(1 << 32));
+        assert_eq!(all.v6_address_count().get(), None);
+        assert_eq!(
+            all.v6_address_count().to_string(),
+            "340282366920938463463374607431768211456"
+        );
+        assert_eq!(IpBothRange::new().v6_address_count().get(), Some(0));
+    }
     #[test]
     fn parse_single_prefixlen() {
         let pfxlen: PrefixlenPair = "20".parse().unwrap();
@@ -0,0 +1,32 @@
+//! Aggregate lists of IP prefixes into their minimum equivalent representation.
+//!
+//! This is the library behind the `rs-aggregate` command line tool, so linking
+//! against it gives exactly the same parsing and aggregation behaviour.
+//!
+//! ```
+//! use rs_aggregate::{IpBothRange, IpOrNet};
+//!
+//! let mut prefixes: IpBothRange = ["192.0.2.0/25", "192.0.2.128/25", "2001:db8::/32"]
+//!     .iter()
+//!     .map(|s| s.parse::<IpOrNet>().unwrap())
+//!     .collect();
+//! prefixes.simplify();
+//! assert_eq!(prefixes.to_string(), "192.0.2.0/24\n2001:db8::/32\n");
+//! ```
+
+mod budget;
+mod coverage;
+mod fib;
+mod iputils;
+pub mod output;
+mod prefixrange;
+mod ranges;
+pub mod stats;
+
+pub use fib::RouteTable;
+pub use ipnet;
+pub use iputils::{
+    AddressCount, Approximation, EntryLimit, IpBothRange, IpBothRangeIter, IpOrNet, IpRange,
+    NetParseError, ParseEntryLimitError, ParsePrefixlenError, PrefixlenPair,
+};
+pub use prefixrange::PrefixRange;
@@ -1,100 +1,626 @@
 extern crate ipnet;
 
-use std::{io, process::exit};
+use std::{borrow::Cow, collections::BTreeMap, fmt::Display, io, num::NonZeroU32, process::exit};
 
-mod iputils;
-use iputils::{IpBothRange, IpOrNet, PrefixlenPair};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use rs_aggregate::output::{self, Firewall, FirewallOptions, PrefixListOptions, Router};
+use rs_aggregate::stats::Stats;
+use rs_aggregate::{
+    EntryLimit, IpBothRange, IpOrNet, IpRange, PrefixRange, PrefixlenPair, RouteTable,
+};
 
 use clio::*;
 use std::io::{BufRead, Write};
 
-use clap::Parser;
+use clap::parser::{ArgMatches, ValueSource};
+use clap::{ArgGroup, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 
 const WRITER_BUFSIZE: usize = 16 * 1024;
 
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// One prefix per line
+    #[default]
+    Cidr,
+    /// One `first-last` range of contiguous addresses per line
+    Range,
+    /// A JSON array of objects describing each prefix
+    Json,
+    /// One JSON object describing each prefix per line
+    #[value(name = "jsonl")]
+    JsonLines,
+    /// Cisco IOS `ip prefix-list` / `ipv6 prefix-list`
+    CiscoIos,
+    /// Cisco IOS-XR `prefix-set`
+    CiscoXr,
+    /// Junos `policy-options prefix-list` set commands
+    Junos,
+    /// Arista EOS `ip prefix-list` / `ipv6 prefix-list`
+    AristaEos,
+    /// BIRD prefix set `define`s
+    Bird,
+    /// FRRouting `ip prefix-list` / `ipv6 prefix-list`
+    Frr,
+    /// nftables script replacing the contents of interval sets
+    #[value(name = "nft")]
+    Nftables,
+    /// `ipset restore` script replacing the contents of hash:net sets
+    Ipset,
+    /// `iptables` / `ip6tables` commands dropping traffic from each prefix
+    Iptables,
+    /// pf table file
+    Pf,
+}
+
+impl OutputFormat {
+    fn router(&self) -> Option<Router> {
+        match self {
+            OutputFormat::CiscoIos => Some(Router::CiscoIos),
+            OutputFormat::CiscoXr => Some(Router::CiscoXr),
+            OutputFormat::Junos => Some(Router::Junos),
+            OutputFormat::AristaEos => Some(Router::AristaEos),
+            OutputFormat::Bird => Some(Router::Bird),
+            OutputFormat::Frr => Some(Router::Frr),
+            _ => None,
+        }
+    }
+    fn firewall(&self) -> Option<Firewall> {
+        match self {
+            OutputFormat::Nftables => Some(Firewall::Nftables),
+            OutputFormat::Ipset => Some(Firewall::Ipset),
+            OutputFormat::Iptables => Some(Firewall::Iptables),
+            OutputFormat::Pf => Some(Firewall::Pf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Report the address space added and removed between two prefix lists
+    Diff {
+        /// The earlier prefix list
+        old: Input,
+        /// The later prefix list
+        new: Input,
+    },
+    /// Compress `prefix next-hop` routes into the smallest table that forwards
+    /// every address the same way
+    Fib {
+        #[clap(value_parser, default_value = "-")]
+        input: Vec<Input>,
+    },
+    /// Aggregate `prefix origin-asn` lines separately for each origin AS,
+    /// writing them grouped by ASN
+    Origin {
+        #[clap(value_parser, default_value = "-")]
+        input: Vec<Input>,
+    },
+}
+
 #[derive(Parser)]
-#[command(author, version, about)]
+#[command(author, version, about, group(ArgGroup::new("splitting").multiple(true)))]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[clap(value_parser, default_value = "-")]
     input: Vec<Input>,
     /// Maximum prefix length for prefixes read. Single value applies to IPv4 and IPv6, comma-separated [IPv4],[IPv6].
-    #[structopt(short, long, default_value = "32,128")]
+    #[structopt(short, long, default_value = "32,128", global = true)]
     max_prefixlen: PrefixlenPair,
     /// Truncate IP/mask to network/mask (else ignore)
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     truncate: bool,
+    /// Replace each prefix read with its covering prefix of this length, if
+    /// longer. Single value applies to IPv4 and IPv6, comma-separated [IPv4],[IPv6]
+    #[arg(short, long, value_name = "LEN", global = true)]
+    widen: Option<PrefixlenPair>,
     /// Only output IPv4 prefixes
-    #[arg(id = "4", short, conflicts_with("6"))]
+    #[arg(id = "4", short, conflicts_with("6"), global = true)]
     only_v4: bool,
     /// Only output IPv6 prefixes
-    #[arg(id = "6", short, conflicts_with("4"))]
+    #[arg(id = "6", short, conflicts_with("4"), global = true)]
     only_v6: bool,
+    /// Only remove prefixes covered by another prefix, keeping adjacent
+    /// prefixes separate rather than merging them
+    #[arg(
+        long,
+        conflicts_with_all(["intersect", "exclude", "complement", "coverage", "max_entries"])
+    )]
+    redundant_only: bool,
+    /// Output prefix-list entries with ge/le length ranges that match exactly
+    /// the prefixes read, rather than the address space they cover
+    #[arg(
+        long,
+        conflicts_with_all([
+            "verbose",
+            "stats",
+            "redundant_only",
+            "intersect",
+            "exclude",
+            "complement",
+            "coverage",
+            "max_entries",
+            "min_output_prefixlen",
+            "split",
+            "update_from",
+        ])
+    )]
+    length_ranges: bool,
+    /// Only output address space that is also listed in this file (may be repeated)
+    #[arg(long, value_name = "FILE")]
+    intersect: Vec<Input>,
+    /// Remove the address space listed in this file from the output (may be repeated)
+    #[arg(short = 'x', long, value_name = "FILE")]
+    exclude: Vec<Input>,
+    /// Output the address space not covered by the aggregated prefixes
+    #[arg(short, long)]
+    complement: bool,
+    /// Restrict --complement to the address space listed in this file (may be repeated)
+    #[arg(long, value_name = "FILE", requires("complement"))]
+    universe: Vec<Input>,
+    /// Replace prefixes with their covering supernet wherever they cover at
+    /// least this percentage of it
+    #[arg(long, value_name = "PCT", value_parser = parse_percent)]
+    coverage: Option<f64>,
+    /// Shortest prefix length --coverage may collapse into. Single value
+    /// applies to IPv4 and IPv6, comma-separated [IPv4],[IPv6]
+    #[arg(
+        long,
+        value_name = "LEN",
+        default_value = "24,48",
+        requires("coverage")
+    )]
+    coverage_prefixlen: PrefixlenPair,
+    /// Merge prefixes into covering supernets, adding as few addresses as
+    /// possible, until there are at most this many per family. Single value
+    /// applies to IPv4 and IPv6, comma-separated [IPv4],[IPv6]
+    #[arg(long, value_name = "N")]
+    max_entries: Option<EntryLimit>,
+    /// Split output prefixes shorter than this into prefixes of this length.
+    /// Single value applies to IPv4 and IPv6, comma-separated [IPv4],[IPv6]
+    #[arg(
+        long,
+        value_name = "LEN",
+        group = "splitting",
+        conflicts_with("max_entries")
+    )]
+    min_output_prefixlen: Option<PrefixlenPair>,
+    /// Output every subnet of this length of each aggregated prefix instead.
+    /// Single value applies to IPv4 and IPv6, comma-separated [IPv4],[IPv6]
+    #[arg(
+        long,
+        value_name = "LEN",
+        group = "splitting",
+        conflicts_with_all(["verbose", "stats", "update_from"])
+    )]
+    split: Option<PrefixlenPair>,
+    /// Abort --split or --min-output-prefixlen rather than output more than
+    /// this many prefixes
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1_000_000,
+        requires("splitting")
+    )]
+    split_limit: u128,
+    /// Output format
+    #[arg(short = 'F', long, value_enum, default_value_t)]
+    format: OutputFormat,
+    /// nftables family and table holding the generated sets
+    #[arg(long, value_name = "TABLE", default_value = "inet filter")]
+    nft_table: String,
+    /// Write only the changes needed to update sets holding the prefixes in
+    /// this file, either the previous output or a list of prefixes. Requires
+    /// `--format nft` or `ipset`
+    #[arg(long, value_name = "FILE")]
+    update_from: Option<Input>,
+    /// Name of the generated prefix-list, set or chain
+    #[arg(long, value_name = "NAME", default_value = "AGGREGATE")]
+    list_name: String,
+    /// Number prefix-list entries in steps of N, where the format supports it
+    #[arg(long, value_name = "N", value_parser = parse_sequence)]
+    sequence: Option<NonZeroU32>,
+    /// Display verbose information about the optimisations: '-' for removed
+    /// input prefixes, '+' for new aggregates, unprefixed for unchanged ones
+    #[arg(short, long, conflicts_with("format"))]
+    verbose: bool,
+    /// Report prefix counts, address totals and prefix lengths before and
+    /// after aggregation instead of the prefixes
+    #[arg(long, conflicts_with_all(["format", "verbose"]))]
+    stats: bool,
+    /// Exit with a non-zero status, without output, if any input can't be used
+    #[arg(long, global = true)]
+    strict: bool,
+    /// Abort after this many input errors
+    #[arg(long, value_name = "N", global = true)]
+    max_errors: Option<usize>,
 }
 
 impl Default for Args {
     fn default() -> Self {
         Args {
+            command: None,
             input: Vec::from([clio::Input::default()]),
             max_prefixlen: PrefixlenPair::default(),
             truncate: false,
+            widen: None,
             only_v4: false,
             only_v6: false,
+            redundant_only: false,
+            length_ranges: false,
+            intersect: Vec::new(),
+            exclude: Vec::new(),
+            complement: false,
+            universe: Vec::new(),
+            coverage: None,
+            coverage_prefixlen: PrefixlenPair { v4: 24, v6: 48 },
+            max_entries: None,
+            min_output_prefixlen: None,
+            split: None,
+            split_limit: 1_000_000,
+            format: OutputFormat::default(),
+            nft_table: FirewallOptions::default().nft_table,
+            update_from: None,
+            list_name: PrefixListOptions::default().name,
+            sequence: None,
+            verbose: false,
+            stats: false,
+            strict: false,
+            max_errors: None,
         }
     }
 }
 
-#[derive(Parser)]
+fn parse_percent(s: &str) -> std::result::Result<f64, String> {
+    let percent: f64 = s
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| String::from("Unable to parse percentage"))?;
+    if percent > 0.0 && percent <= 100.0 {
+        Ok(percent)
+    } else {
+        Err(String::from(
+            "Percentage must be greater than 0 and at most 100",
+        ))
+    }
+}
+
+/// Number of words a prefix length qualifier starting with `word` takes up,
+/// or 0 if `word` doesn't start one
+fn qualifier_words(word: &str) -> usize {
+    match word.trim_end_matches(';') {
+        "ge" | "le" | "eq" | "upto" | "prefix-length-range" => 2,
+        // A lone `;` ends a Junos route-filter
+        "exact" | "orlonger" | "longer" | "" => 1,
+        _ => 0,
+    }
+}
+
+/// Exit with a usage error if any option that only applies to the top-level
+/// command was given along with subcommand `name`. Global options apply to
+/// every subcommand.
+fn reject_unused_args(matches: &ArgMatches, name: &str) {
+    let mut cmd = Args::command();
+    let unused = cmd.get_arguments().find(|arg| {
+        !arg.is_global_set()
+            && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+    });
+    if let Some(arg) = unused {
+        let arg = match arg.get_long() {
+            Some(long) => format!("--{}", long),
+            None => arg.get_id().to_string().to_uppercase(),
+        };
+        cmd.error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!("{} can't be used with the {} subcommand", arg, name),
+        )
+        .exit();
+    }
+}
+
+fn parse_sequence(s: &str) -> std::result::Result<NonZeroU32, String> {
+    let step: u32 = s
+        .parse()
+        .map_err(|_| String::from("Unable to parse sequence step"))?;
+    NonZeroU32::new(step).ok_or_else(|| String::from("Sequence step must be at least 1"))
+}
+
+/// An AS number, with or without an `AS` prefix
+fn parse_asn(s: &str) -> Option<u32> {
+    let digits = match s.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("AS") => &s[2..],
+        _ => s,
+    };
+    digits.parse().ok()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ErrorKind {
+    InvalidNetwork,
+    InvalidRange,
+    InvalidPrefixRange,
+    InvalidRoute,
+    InvalidOrigin,
+    HostBits,
+}
+
+impl ErrorKind {
+    fn message(&self) -> &'static str {
+        match self {
+            // aggregate6 doesn't distinguish host bits from other invalid networks
+            ErrorKind::InvalidNetwork | ErrorKind::HostBits => "is not a valid IP network",
+            ErrorKind::InvalidRange => "is not a valid IP range",
+            ErrorKind::InvalidPrefixRange => "is not a valid prefix length range",
+            ErrorKind::InvalidRoute => "is not a valid 'prefix next-hop' route",
+            ErrorKind::InvalidOrigin => "is not a valid 'prefix origin-asn' entry",
+        }
+    }
+    fn summary(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidNetwork => "invalid IP network",
+            ErrorKind::InvalidRange => "invalid IP range",
+            ErrorKind::HostBits => "host bits set",
+            ErrorKind::InvalidPrefixRange => "invalid prefix length range",
+            ErrorKind::InvalidRoute => "invalid route",
+            ErrorKind::InvalidOrigin => "invalid origin AS entry",
+        }
+    }
+}
 
+/// An input token that couldn't be used, and where it was found
 struct IpParseError {
+    source: String,
+    line: usize,
     ip: String,
-    problem: String,
+    problem: ErrorKind,
+}
+
+impl Display for IpParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: '{}' {}",
+            self.source,
+            self.line,
+            self.ip,
+            self.problem.message()
+        )
+    }
+}
+
+/// Name of `input` for error messages
+fn source_name(input: &Input) -> String {
+    match input.is_std() {
+        true => String::from("<stdin>"),
+        false => input.path().as_os_str().to_string_lossy().into_owned(),
+    }
+}
+
+/// Picks the prefixes out of a script written by `--format nft` or `ipset`, so
+/// the previous output can be read back by `--update-from`. Only elements of
+/// the sets named by `--list-name` are taken. Any other line is read as a
+/// plain list of prefixes.
+struct PreviousScript {
+    firewall: Firewall,
+    sets: [String; 2],
+    /// Inside the braces of an nft `add element` command, and whether it is
+    /// for one of `sets`
+    in_elements: Option<bool>,
+}
+
+impl PreviousScript {
+    fn new(firewall: Firewall, name: &str) -> Self {
+        PreviousScript {
+            firewall,
+            sets: [format!("{}_v4", name), format!("{}_v6", name)],
+            in_elements: None,
+        }
+    }
+    fn is_ours(&self, set: Option<&str>) -> bool {
+        set.is_some_and(|set| self.sets.iter().any(|name| name == set))
+    }
+    /// The part of `line` holding prefixes, if any
+    fn prefixes<'a>(&mut self, line: &'a str) -> Cow<'a, str> {
+        let line = line.trim();
+        match self.firewall {
+            Firewall::Nftables => {
+                if let Some(ours) = self.in_elements {
+                    let elements = match line.split_once('}') {
+                        Some((elements, _)) => {
+                            self.in_elements = None;
+                            elements
+                        }
+                        None => line,
+                    };
+                    return match ours {
+                        true => Cow::from(elements.replace(',', " ")),
+                        false => Cow::from(""),
+                    };
+                }
+                if line.starts_with("add element ") {
+                    if let Some((command, elements)) = line.split_once('{') {
+                        self.in_elements = Some(self.is_ours(command.split_whitespace().last()));
+                        return self.prefixes(elements);
+                    }
+                }
+                if line.starts_with("add set ") || line.starts_with("flush set ") {
+                    return Cow::from("");
+                }
+            }
+            Firewall::Ipset => {
+                let words: Vec<&str> = line.split_ascii_whitespace().collect();
+                match words[..] {
+                    ["add", set, net, ..] if self.is_ours(Some(set)) => return Cow::from(net),
+                    ["add", _, _, ..] | ["create", ..] | ["flush", ..] => return Cow::from(""),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+        Cow::from(line)
+    }
 }
 
-// type Errors = Vec<IpParseError>;
+/// Number of input errors seen of each kind
+type Errors = BTreeMap<ErrorKind, usize>;
 
 #[derive(Default)]
 struct App {
     args: Args,
     prefixes: IpBothRange,
-    // errors: Errors,
+    /// Prefixes as read, before widening and aggregation, kept for verbose and
+    /// stats output
+    input_prefixes: IpBothRange,
+    /// Entries with length qualifiers read with --length-ranges
+    prefix_ranges: Vec<PrefixRange>,
+    errors: Errors,
 }
 
 impl App {
-    fn add_prefix<const TRUNCATE: bool>(&mut self, pfx: IpOrNet) {
-        // Parser accepts host bits set, so detect that case and error if not truncate mode
-        // Note: aggregate6 errors in this case regardless of -4, -6 so do the same
-        if !TRUNCATE && pfx.has_host_bits() {
-            // We don't have the original string any more so our error
-            // differs from `aggregate6` in that it prints the pfxlen as
-            // parsed, not as in the source.
-            eprintln!("ERROR: '{}' is not a valid IP network, ignoring.", pfx);
-            return;
+    /// Whether `pfx` is in an address family selected by `-4` / `-6`
+    fn wanted_family(&self, pfx: &IpOrNet) -> bool {
+        !(self.args.only_v4 && pfx.is_ipv6() || self.args.only_v6 && pfx.is_ipv4())
+    }
+    fn add_prefix(&mut self, pfx: IpOrNet) {
+        if self.wanted_family(&pfx) && self.args.max_prefixlen >= pfx {
+            if self.args.verbose || self.args.stats {
+                self.input_prefixes.add(pfx);
+            }
+            match &self.args.widen {
+                Some(len) => self.prefixes.add(pfx.widen(len)),
+                None => self.prefixes.add(pfx),
+            }
         }
-
-        if self.args.only_v4 && pfx.is_ipv6() {
+    }
+    /// Keep an entry read with --length-ranges, limited to the selected address
+    /// families and prefix lengths
+    fn add_prefix_range(&mut self, range: PrefixRange) {
+        if !self.wanted_family(&range.prefix().into()) {
             return;
         }
-        if self.args.only_v6 && pfx.is_ipv4() {
+        let max_len = match range.prefix() {
+            IpNet::V4(_) => self.args.max_prefixlen.v4,
+            IpNet::V6(_) => self.args.max_prefixlen.v6,
+        };
+        // Fails if every length is over the limit
+        let Ok(range) = PrefixRange::new(
+            range.prefix(),
+            range.min_len(),
+            range.max_len().min(max_len),
+        ) else {
             return;
+        };
+        match &self.args.widen {
+            Some(len) => self.prefix_ranges.push(range.widen(len)),
+            None => self.prefix_ranges.push(range),
+        }
+    }
+    /// Parse one whitespace-separated token, handing the valid prefixes to `add`.
+    fn consume_token<const TRUNCATE: bool>(
+        &mut self,
+        token: &str,
+        add: &mut impl FnMut(&mut Self, IpOrNet),
+    ) -> std::result::Result<(), ErrorKind> {
+        let spaced_range = token.contains(" -");
+        if !spaced_range && token.contains(' ') || token.ends_with(';') {
+            return self.consume_prefix_range::<TRUNCATE>(token, add);
         }
-        if self.args.max_prefixlen >= pfx {
-            self.prefixes.add(pfx);
+        // Neither address family uses '-', so this can only be a range
+        if token.contains('-') {
+            let range = token.parse::<IpRange>().or(Err(ErrorKind::InvalidRange))?;
+            for pnet in range.prefixes() {
+                add(self, pnet);
+            }
+            return Ok(());
         }
+        let pnet = token
+            .parse::<IpOrNet>()
+            .or(Err(ErrorKind::InvalidNetwork))?;
+        // Parser accepts host bits set, so detect that case and error if not truncate mode
+        // Note: aggregate6 errors in this case regardless of -4, -6 so do the same
+        if !TRUNCATE && pnet.has_host_bits() {
+            return Err(ErrorKind::HostBits);
+        }
+        add(self, pnet);
+        Ok(())
     }
-    fn consume_input<const TRUNCATE: bool>(&mut self, input: &mut Input) {
-        for line in input.lock().lines() {
-            match line {
-                Ok(line) => {
-                    for net in line.split_ascii_whitespace() {
-                        let pnet = net.parse::<IpOrNet>();
-                        match pnet {
-                            Ok(pnet) => self.add_prefix::<TRUNCATE>(pnet),
-                            Err(_e) => {
-                                eprintln!("ERROR: '{}' is not a valid IP network, ignoring.", net);
-                            }
-                        }
+    /// Parse a prefix with length qualifiers, like `10.0.0.0/8 le 24`. With
+    /// --length-ranges the entry is kept as it is, otherwise just the covering
+    /// prefix is handed to `add`.
+    fn consume_prefix_range<const TRUNCATE: bool>(
+        &mut self,
+        token: &str,
+        add: &mut impl FnMut(&mut Self, IpOrNet),
+    ) -> std::result::Result<(), ErrorKind> {
+        let mut range = token
+            .parse::<PrefixRange>()
+            .or(Err(ErrorKind::InvalidPrefixRange))?;
+        if IpOrNet::from(range.prefix()).has_host_bits() {
+            if !TRUNCATE {
+                return Err(ErrorKind::HostBits);
+            }
+            range = range.trunc();
+        }
+        match self.args.length_ranges {
+            true => self.add_prefix_range(range),
+            false => add(self, range.prefix().into()),
+        }
+        Ok(())
+    }
+    /// Parse every prefix in one line of input, handing the valid ones to
+    /// `add`.
+    fn consume_line<const TRUNCATE: bool>(
+        &mut self,
+        source: &str,
+        lineno: usize,
+        line: &str,
+        add: &mut impl FnMut(&mut Self, IpOrNet),
+    ) {
+        let mut words = line.split_ascii_whitespace().peekable();
+        while let Some(word) = words.next() {
+            let mut token = Cow::from(word);
+            // RIPE and APNIC inetnum ranges are written `first - last`
+            if words.next_if_eq(&"-").is_some() {
+                let token = token.to_mut();
+                token.push_str(" -");
+                if let Some(last) = words.next() {
+                    token.push(' ');
+                    token.push_str(last);
+                }
+            }
+            // Keep length qualifiers like `le 24` with the prefix before them
+            while let Some(qualifier) = words.next_if(|w| qualifier_words(w) > 0) {
+                let token = token.to_mut();
+                token.push(' ');
+                token.push_str(qualifier);
+                if qualifier_words(qualifier) > 1 {
+                    if let Some(arg) = words.next() {
+                        token.push(' ');
+                        token.push_str(arg);
                     }
                 }
+            }
+            if let Err(problem) = self.consume_token::<TRUNCATE>(&token, add) {
+                self.report(IpParseError {
+                    source: source.to_owned(),
+                    line: lineno + 1,
+                    ip: token.into_owned(),
+                    problem,
+                });
+            }
+        }
+    }
+    /// Parse every prefix in `input`, handing the valid ones to `add`.
+    fn consume_input<const TRUNCATE: bool>(
+        &mut self,
+        input: &mut Input,
+        add: &mut impl FnMut(&mut Self, IpOrNet),
+    ) {
+        let source = source_name(input);
+        for (lineno, line) in input.lock().lines().enumerate() {
+            match line {
+                Ok(line) => self.consume_line::<TRUNCATE>(&source, lineno, &line, add),
                 Err(e) => {
                     eprintln!("I/O error! {}", e);
                     exit(1);
@@ -102,27 +628,530 @@ impl App {
             }
         }
     }
-    fn simplify_inputs(&mut self) {
+    /// Parse one `prefix value` line, returning `None` for blank lines
+    fn parse_pair<'a>(
+        &self,
+        line: &'a str,
+        invalid: ErrorKind,
+    ) -> std::result::Result<Option<(IpOrNet, &'a str)>, ErrorKind> {
+        let mut words = line.split_ascii_whitespace();
+        let (prefix, value) = match (words.next(), words.next(), words.next()) {
+            (None, _, _) => return Ok(None),
+            (Some(prefix), Some(value), None) => (prefix, value),
+            _ => return Err(invalid),
+        };
+        let pnet = prefix
+            .parse::<IpOrNet>()
+            .or(Err(ErrorKind::InvalidNetwork))?;
+        if !self.args.truncate && pnet.has_host_bits() {
+            return Err(ErrorKind::HostBits);
+        }
+        Ok(Some((pnet, value)))
+    }
+    /// Read `prefix value` lines from `input`, handing those for the selected
+    /// address families and prefix lengths to `add`, widened if requested. Lines that aren't in
+    /// this form are reported as `invalid`.
+    fn read_pairs(
+        &mut self,
+        input: &mut Input,
+        invalid: ErrorKind,
+        add: &mut impl FnMut(IpOrNet, &str) -> std::result::Result<(), ErrorKind>,
+    ) {
+        let source = source_name(input);
+        for (lineno, line) in input.lock().lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("I/O error! {}", e);
+                    exit(1);
+                }
+            };
+            let result = match self.parse_pair(&line, invalid) {
+                Ok(Some((pnet, value)))
+                    if self.wanted_family(&pnet) && self.args.max_prefixlen >= pnet =>
+                {
+                    match &self.args.widen {
+                        Some(len) => add(pnet.widen(len), value),
+                        None => add(pnet, value),
+                    }
+                }
+                Ok(_) => Ok(()),
+                Err(problem) => Err(problem),
+            };
+            if let Err(problem) = result {
+                self.report(IpParseError {
+                    source: source.clone(),
+                    line: lineno + 1,
+                    ip: line.trim().to_owned(),
+                    problem,
+                });
+            }
+        }
+    }
+    fn report(&mut self, err: IpParseError) {
+        // Strict mode fails instead of carrying on without the input
+        match self.args.strict {
+            true => eprintln!("ERROR: {}.", err),
+            false => eprintln!("ERROR: {}, ignoring.", err),
+        }
+        *self.errors.entry(err.problem).or_default() += 1;
+        if let Some(max) = self.args.max_errors {
+            if self.errors.values().sum::<usize>() >= max {
+                eprintln!("ERROR: Too many errors, aborting.");
+                self.print_error_summary();
+                exit(1);
+            }
+        }
+    }
+    fn print_error_summary(&self) {
+        let total: usize = self.errors.values().sum();
+        eprintln!("{} input error(s):", total);
+        for (kind, count) in &self.errors {
+            eprintln!("  {} {}", count, kind.summary());
+        }
+    }
+    fn read_input(&mut self, input: &mut Input, add: &mut impl FnMut(&mut Self, IpOrNet)) {
+        match self.args.truncate {
+            true => self.consume_input::<true>(input, add),
+            false => self.consume_input::<false>(input, add),
+        }
+    }
+    /// Read a list of inputs into a new set, without the output filters applied.
+    fn read_set(&mut self, inputs: Vec<Input>) -> IpBothRange {
+        let mut set = IpBothRange::new();
+        for mut input in inputs {
+            self.read_input(&mut input, &mut |_, pfx| set.add(pfx));
+        }
+        set
+    }
+    fn read_inputs(&mut self) {
         let inputs = self.args.input.to_owned();
         for mut input in inputs {
+            self.read_input(&mut input, &mut Self::add_prefix);
+        }
+    }
+    fn simplify_inputs(&mut self) {
+        self.read_inputs();
+        if self.args.redundant_only {
+            self.prefixes.remove_redundant();
+        } else {
+            self.prefixes.simplify();
+        }
+
+        let groups = self.args.intersect.to_owned();
+        for input in groups {
+            let group = self.read_set(vec![input]);
+            self.prefixes = self.prefixes.intersection(&group);
+        }
+        if !self.args.exclude.is_empty() {
+            let exclude = self.read_set(self.args.exclude.to_owned());
+            self.prefixes = self.prefixes.difference(&exclude);
+        }
+        if self.args.complement {
+            let universe = self.read_universe();
+            self.prefixes = universe.difference(&self.prefixes);
+        }
+        if let Some(percent) = self.args.coverage {
+            let approximations = self
+                .prefixes
+                .approximate(percent / 100.0, &self.args.coverage_prefixlen);
+            for approx in approximations {
+                let gaps: Vec<String> = approx.gaps.iter().map(IpNet::to_string).collect();
+                eprintln!(
+                    "WARNING: Approximated {} ({:.1}% covered), adding {}.",
+                    approx.supernet,
+                    approx.coverage() * 100.0,
+                    gaps.join(", ")
+                );
+            }
+        }
+        if let Some(limit) = &self.args.max_entries {
+            let (v4_added, v6_added) = self.prefixes.fit_entries(limit);
+            for (family, max, added) in [("IPv4", limit.v4, v4_added), ("IPv6", limit.v6, v6_added)]
+            {
+                if added.get() != Some(0) {
+                    eprintln!(
+                        "WARNING: Fitting {} in {} entries added {} addresses.",
+                        family, max, added
+                    );
+                }
+            }
+        }
+        if let Some(min_len) = &self.args.min_output_prefixlen {
+            self.check_split_count(min_len);
+            self.prefixes.split_shorter_than(min_len);
+        }
+    }
+    /// Read a list of inputs into a new set, keeping only the address
+    /// families selected by `-4` / `-6`.
+    fn read_family_set(&mut self, inputs: Vec<Input>) -> IpBothRange {
+        let mut set = IpBothRange::new();
+        for mut input in inputs {
+            self.read_input(&mut input, &mut |app, pfx| {
+                if app.wanted_family(&pfx) {
+                    set.add(pfx)
+                }
+            });
+        }
+        set
+    }
+    /// Read the `--update-from` file, which may be a script written with the
+    /// selected `--format`, keeping only the address families selected by
+    /// `-4` / `-6`.
+    fn read_previous(&mut self, mut input: Input) -> IpBothRange {
+        let mut script =
+            PreviousScript::new(self.args.format.firewall().unwrap(), &self.args.list_name);
+        let source = source_name(&input);
+        let mut set = IpBothRange::new();
+        let add = &mut |app: &mut Self, pfx| {
+            if app.wanted_family(&pfx) {
+                set.add(pfx)
+            }
+        };
+        for (lineno, line) in input.lock().lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("I/O error! {}", e);
+                    exit(1);
+                }
+            };
+            let prefixes = script.prefixes(&line);
             match self.args.truncate {
-                true => self.consume_input::<true>(&mut input),
-                false => self.consume_input::<false>(&mut input),
+                true => self.consume_line::<true>(&source, lineno, &prefixes, add),
+                false => self.consume_line::<false>(&source, lineno, &prefixes, add),
             }
         }
-        self.prefixes.simplify();
+        set
+    }
+    /// The address space that `--complement` inverts within
+    fn read_universe(&mut self) -> IpBothRange {
+        if !self.args.universe.is_empty() {
+            return self.read_family_set(self.args.universe.to_owned());
+        }
+        let mut universe = IpBothRange::new();
+        for pfx in [Ipv4Net::default().into(), Ipv6Net::default().into()] {
+            if self.wanted_family(&pfx) {
+                universe.add(pfx);
+            }
+        }
+        universe
+    }
+
+    /// Write the union of input and output prefixes in `aggregate6 -v` style,
+    /// sorted so each removed prefix follows the aggregate that replaced it.
+    fn write_verbose(&self, w: &mut impl Write) -> io::Result<()> {
+        // (in input, in output) for each prefix
+        let mut seen: BTreeMap<IpNet, (bool, bool)> = BTreeMap::new();
+        for net in &self.input_prefixes {
+            seen.entry(net.trunc()).or_default().0 = true;
+        }
+        for net in &self.prefixes {
+            seen.entry(net).or_default().1 = true;
+        }
+        for (net, state) in seen {
+            match state {
+                (true, false) => writeln!(w, "- {}", net)?,
+                (false, true) => writeln!(w, "+ {}", net)?,
+                _ => writeln!(w, "  {}", net)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn firewall_options(&self) -> FirewallOptions {
+        FirewallOptions {
+            name: self.args.list_name.clone(),
+            nft_table: self.args.nft_table.clone(),
+        }
+    }
+
+    fn write_output(&mut self, w: &mut impl Write) -> io::Result<()> {
+        if let Some(previous) = self.args.update_from.to_owned() {
+            let previous = self.read_previous(previous);
+            self.check_errors();
+            let (removed, added) = previous.changes_to(&self.prefixes);
+            return output::write_firewall_update(
+                w,
+                self.args.format.firewall().unwrap(),
+                &self.firewall_options(),
+                &removed,
+                &added,
+            );
+        }
+        match (self.args.format, &self.args.split) {
+            (OutputFormat::Range, _) => {
+                for range in self.prefixes.ranges() {
+                    writeln!(w, "{}", range)?;
+                }
+                Ok(())
+            }
+            (_, Some(len)) => self.write_prefixes(
+                w,
+                self.prefixes.v4_split(len.v4),
+                self.prefixes.v6_split(len.v6),
+            ),
+            (_, None) => self.write_prefixes(
+                w,
+                self.prefixes.v4_iter().copied(),
+                self.prefixes.v6_iter().copied(),
+            ),
+        }
+    }
+
+    /// Write `v4` then `v6` in the selected format.
+    fn write_prefixes(
+        &self,
+        w: &mut impl Write,
+        v4: impl Iterator<Item = Ipv4Net>,
+        v6: impl Iterator<Item = Ipv6Net>,
+    ) -> io::Result<()> {
+        match self.args.format {
+            OutputFormat::Cidr => {
+                for net in v4.map(IpNet::from).chain(v6.map(IpNet::from)) {
+                    writeln!(w, "{}", net)?;
+                }
+                Ok(())
+            }
+            OutputFormat::Json => {
+                output::write_json(w, v4.map(IpNet::from).chain(v6.map(IpNet::from)))
+            }
+            OutputFormat::JsonLines => {
+                output::write_json_lines(w, v4.map(IpNet::from).chain(v6.map(IpNet::from)))
+            }
+            format if format.firewall().is_some() => output::write_firewall(
+                w,
+                format.firewall().unwrap(),
+                &self.firewall_options(),
+                v4,
+                v6,
+            ),
+            format => {
+                let router = format.router().expect("all other formats are prefix-lists");
+                let opts = PrefixListOptions {
+                    name: self.args.list_name.clone(),
+                    sequence: self.args.sequence,
+                };
+                output::write_prefix_list(w, router, &opts, v4, v6)
+            }
+        }
+    }
+
+    /// Write prefix-list entries with length ranges matching the prefixes read
+    fn write_length_ranges(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut entries = self.prefix_ranges.clone();
+        entries.extend((&self.prefixes).into_iter().map(PrefixRange::from));
+        let (v4, v6): (Vec<_>, Vec<_>) = (PrefixRange::compress(&entries).into_iter())
+            .partition(|range| matches!(range.prefix(), IpNet::V4(_)));
+        match self.args.format.router() {
+            Some(router) => {
+                let opts = PrefixListOptions {
+                    name: self.args.list_name.clone(),
+                    sequence: self.args.sequence,
+                };
+                output::write_prefix_range_list(w, router, &opts, v4, v6)
+            }
+            None => {
+                for range in v4.into_iter().chain(v6) {
+                    writeln!(w, "{}", range)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Read one input with the same filters as the main inputs, aggregated
+    fn read_aggregated(&mut self, mut input: Input) -> IpBothRange {
+        self.read_input(&mut input, &mut Self::add_prefix);
+        let mut set = std::mem::take(&mut self.prefixes);
+        set.simplify();
+        set
+    }
+
+    /// Write the address space removed and added going from `old` to `new`,
+    /// in address order, followed by a summary.
+    fn write_diff(&mut self, w: &mut impl Write, old: Input, new: Input) -> io::Result<()> {
+        let old = self.read_aggregated(old);
+        let new = self.read_aggregated(new);
+        self.check_errors();
+        let removed = old.difference(&new);
+        let added = new.difference(&old);
+
+        let mut changes: Vec<(IpNet, char)> = (&removed)
+            .into_iter()
+            .map(|net| (net, '-'))
+            .chain((&added).into_iter().map(|net| (net, '+')))
+            .collect();
+        changes.sort_unstable();
+        for (net, sign) in changes {
+            writeln!(w, "{} {}", sign, net)?;
+        }
+
+        writeln!(
+            w,
+            "# IPv4: {} prefixes removed ({} addresses), {} prefixes added ({} addresses)",
+            removed.v4_iter().count(),
+            removed.v4_address_count(),
+            added.v4_iter().count(),
+            added.v4_address_count(),
+        )?;
+        writeln!(
+            w,
+            "# IPv6: {} prefixes removed ({} addresses), {} prefixes added ({} addresses)",
+            removed.v6_iter().count(),
+            removed.v6_address_count(),
+            added.v6_iter().count(),
+            added.v6_address_count(),
+        )?;
+        Ok(())
+    }
+
+    /// Stop before splitting the prefixes into subnets of `len` if that would
+    /// give more than --split-limit prefixes
+    fn check_split_count(&self, len: &PrefixlenPair) {
+        match self.prefixes.split_count(len) {
+            Some(count) if count <= self.args.split_limit => (),
+            count => {
+                let count = count.map_or(String::from("at least 2^128"), |c| c.to_string());
+                eprintln!(
+                    "ERROR: Splitting would output {} prefixes, more than --split-limit {}, aborting.",
+                    count, self.args.split_limit
+                );
+                exit(1);
+            }
+        }
+    }
+
+    /// In strict mode, stop before writing anything if there were input errors
+    fn check_errors(&self) {
+        if self.args.strict && !self.errors.is_empty() {
+            self.print_error_summary();
+            exit(1);
+        }
     }
 
     fn main(&mut self) {
-        self.args = Args::parse();
+        let matches = Args::command().get_matches();
+        self.args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        if let Some((name, _)) = matches.subcommand() {
+            reject_unused_args(&matches, name);
+        }
+        if self.args.update_from.is_some()
+            && !matches!(
+                self.args.format,
+                OutputFormat::Nftables | OutputFormat::Ipset
+            )
+        {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "--update-from requires --format nft or --format ipset",
+                )
+                .exit();
+        }
 
-        self.simplify_inputs();
+        // ipset output is sized up front and range output would merge the
+        // subnets straight back together
+        if self.args.split.is_some()
+            && matches!(self.args.format, OutputFormat::Ipset | OutputFormat::Range)
+        {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "--split can't be used with --format ipset or --format range",
+                )
+                .exit();
+        }
+
+        if self.args.length_ranges
+            && !(self.args.format == OutputFormat::Cidr || self.args.format.router().is_some())
+        {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "--length-ranges requires --format cidr or a prefix-list format",
+                )
+                .exit();
+        }
 
         let stdout = io::stdout().lock();
         let mut w = io::BufWriter::with_capacity(WRITER_BUFSIZE, stdout);
-
-        write!(&mut w, "{}", self.prefixes).unwrap();
+        self.run(&mut w);
         w.flush().unwrap();
+        if !self.errors.is_empty() {
+            self.print_error_summary();
+        }
+    }
+
+    /// Read the inputs and write the selected output to `w`
+    fn run(&mut self, w: &mut impl Write) {
+        if self.args.length_ranges {
+            self.read_inputs();
+            self.check_errors();
+            self.write_length_ranges(w).unwrap();
+            return;
+        }
+
+        match self.args.command.take() {
+            Some(Command::Diff { old, new }) => {
+                self.write_diff(w, old, new).unwrap();
+                return;
+            }
+            Some(Command::Fib { input }) => {
+                let mut table = RouteTable::new();
+                for mut input in input {
+                    self.read_pairs(
+                        &mut input,
+                        ErrorKind::InvalidRoute,
+                        &mut |pnet, next_hop| {
+                            table.add(pnet, next_hop);
+                            Ok(())
+                        },
+                    );
+                }
+                self.check_errors();
+                table.compress();
+                write!(w, "{}", table).unwrap();
+                return;
+            }
+            Some(Command::Origin { input }) => {
+                let mut origins: BTreeMap<u32, IpBothRange> = BTreeMap::new();
+                for mut input in input {
+                    self.read_pairs(&mut input, ErrorKind::InvalidOrigin, &mut |pnet, asn| {
+                        let asn = parse_asn(asn).ok_or(ErrorKind::InvalidOrigin)?;
+                        origins.entry(asn).or_default().add(pnet);
+                        Ok(())
+                    });
+                }
+                self.check_errors();
+                for (asn, prefixes) in &mut origins {
+                    prefixes.simplify();
+                    for net in &*prefixes {
+                        writeln!(w, "{} {}", net, asn).unwrap();
+                    }
+                }
+                return;
+            }
+            None => (),
+        }
+
+        self.simplify_inputs();
+        self.check_errors();
+
+        if let Some(len) = &self.args.split {
+            self.check_split_count(len);
+        }
+
+        if self.args.verbose {
+            self.write_verbose(w).unwrap();
+            return;
+        }
+        if self.args.stats {
+            let stats = Stats::new(&self.input_prefixes, &self.prefixes);
+            write!(w, "{}", stats).unwrap();
+            return;
+        }
+        self.write_output(w).unwrap();
     }
 }
 
@@ -0,0 +1,719 @@
+//! Writers for the output formats other than plain CIDR lines, which is the
+//! `Display` impl of [`IpBothRange`](crate::IpBothRange).
+
+use std::io::{self, Write};
+use std::num::NonZeroU32;
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+
+use crate::ranges::Prefix;
+use crate::{AddressCount, IpBothRange, PrefixRange};
+
+/// Write one JSON object describing `net`, without a trailing newline.
+///
+/// The schema is:
+///
+/// ```json
+/// {"network":"192.0.2.0/24","family":"ipv4","prefix_length":24,
+///  "first":"192.0.2.0","last":"192.0.2.255","addresses":"256"}
+/// ```
+///
+/// `addresses` is a string because IPv6 counts overflow 64-bit integers.
+pub fn write_json_object<W: Write>(w: &mut W, net: &IpNet) -> io::Result<()> {
+    let family = match net {
+        IpNet::V4(_) => "ipv4",
+        IpNet::V6(_) => "ipv6",
+    };
+    write!(
+        w,
+        r#"{{"network":"{}","family":"{}","prefix_length":{},"first":"{}","last":"{}","addresses":"{}"}}"#,
+        net.trunc(),
+        family,
+        net.prefix_len(),
+        net.network(),
+        net.broadcast(),
+        AddressCount::from_host_bits(net.max_prefix_len() - net.prefix_len())
+    )
+}
+
+/// Write `nets` as a JSON array, one object per line.
+pub fn write_json<W: Write>(w: &mut W, nets: impl IntoIterator<Item = IpNet>) -> io::Result<()> {
+    w.write_all(b"[")?;
+    for (i, net) in nets.into_iter().enumerate() {
+        w.write_all(if i == 0 { b"\n  " } else { b",\n  " })?;
+        write_json_object(w, &net)?;
+    }
+    w.write_all(b"\n]\n")
+}
+
+/// Write `nets` as JSON Lines, one object per line.
+pub fn write_json_lines<W: Write>(
+    w: &mut W,
+    nets: impl IntoIterator<Item = IpNet>,
+) -> io::Result<()> {
+    for net in nets {
+        write_json_object(w, &net)?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Router configuration dialects that prefix-lists can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Router {
+    /// `ip prefix-list` / `ipv6 prefix-list` lines
+    CiscoIos,
+    /// A single `prefix-set` containing both families
+    CiscoXr,
+    /// `set policy-options prefix-list` commands
+    Junos,
+    /// `ip prefix-list` / `ipv6 prefix-list` blocks
+    AristaEos,
+    /// `define` statements for `_v4` and `_v6` prefix sets
+    Bird,
+    /// `ip prefix-list` / `ipv6 prefix-list` lines
+    Frr,
+}
+
+/// How prefix-lists are named and numbered.
+#[derive(Clone, Debug)]
+pub struct PrefixListOptions {
+    pub name: String,
+    /// Number entries in steps of this size, on dialects that use sequence
+    /// numbers. Numbering restarts for each address family.
+    pub sequence: Option<NonZeroU32>,
+}
+
+impl Default for PrefixListOptions {
+    fn default() -> Self {
+        PrefixListOptions {
+            name: String::from("AGGREGATE"),
+            sequence: None,
+        }
+    }
+}
+
+impl PrefixListOptions {
+    /// `seq N ` for the `i`th entry, if sequence numbers were requested
+    fn seq(&self, i: usize) -> String {
+        match self.sequence {
+            Some(step) => format!("seq {} ", (i as u64 + 1) * u64::from(step.get())),
+            None => String::new(),
+        }
+    }
+}
+
+/// Write prefix-lists matching exactly the given prefixes, as configuration
+/// for `router`.
+pub fn write_prefix_list<W: Write>(
+    w: &mut W,
+    router: Router,
+    opts: &PrefixListOptions,
+    v4: impl IntoIterator<Item = Ipv4Net>,
+    v6: impl IntoIterator<Item = Ipv6Net>,
+) -> io::Result<()> {
+    if router == Router::Junos {
+        let nets = v4
+            .into_iter()
+            .map(IpNet::V4)
+            .chain(v6.into_iter().map(IpNet::V6));
+        for net in nets {
+            writeln!(w, "set policy-options prefix-list {} {}", opts.name, net)?;
+        }
+        return Ok(());
+    }
+    write_prefix_range_list(
+        w,
+        router,
+        opts,
+        v4.into_iter().map(|net| PrefixRange::from(IpNet::V4(net))),
+        v6.into_iter().map(|net| PrefixRange::from(IpNet::V6(net))),
+    )
+}
+
+/// Write prefix-lists made up of the given entries with length ranges, as
+/// configuration for `router`.
+///
+/// Junos prefix-lists can't hold length ranges, so a `route-filter-list` is
+/// written instead.
+pub fn write_prefix_range_list<W: Write>(
+    w: &mut W,
+    router: Router,
+    opts: &PrefixListOptions,
+    v4: impl IntoIterator<Item = PrefixRange>,
+    v6: impl IntoIterator<Item = PrefixRange>,
+) -> io::Result<()> {
+    let name = &opts.name;
+    match router {
+        Router::CiscoIos | Router::Frr => {
+            for (i, range) in v4.into_iter().enumerate() {
+                writeln!(w, "ip prefix-list {} {}permit {}", name, opts.seq(i), range)?;
+            }
+            for (i, range) in v6.into_iter().enumerate() {
+                writeln!(
+                    w,
+                    "ipv6 prefix-list {} {}permit {}",
+                    name,
+                    opts.seq(i),
+                    range
+                )?;
+            }
+        }
+        Router::AristaEos => {
+            let mut v4 = v4.into_iter().peekable();
+            if v4.peek().is_some() {
+                writeln!(w, "ip prefix-list {}", name)?;
+                for (i, range) in v4.enumerate() {
+                    writeln!(w, "   {}permit {}", opts.seq(i), range)?;
+                }
+            }
+            let mut v6 = v6.into_iter().peekable();
+            if v6.peek().is_some() {
+                writeln!(w, "ipv6 prefix-list {}", name)?;
+                for (i, range) in v6.enumerate() {
+                    writeln!(w, "   {}permit {}", opts.seq(i), range)?;
+                }
+            }
+        }
+        Router::CiscoXr => {
+            writeln!(w, "prefix-set {}", name)?;
+            let mut ranges = v4.into_iter().chain(v6).peekable();
+            while let Some(range) = ranges.next() {
+                let sep = if ranges.peek().is_some() { "," } else { "" };
+                writeln!(w, "  {}{}", range, sep)?;
+            }
+            writeln!(w, "end-set")?;
+        }
+        Router::Junos => {
+            for range in v4.into_iter().chain(v6) {
+                writeln!(
+                    w,
+                    "set policy-options route-filter-list {} {} {}",
+                    name,
+                    range.prefix(),
+                    junos_match(&range)
+                )?;
+            }
+        }
+        Router::Bird => {
+            write_bird_set(w, &format!("{}_v4", name), v4.into_iter().map(bird_range))?;
+            write_bird_set(w, &format!("{}_v6", name), v6.into_iter().map(bird_range))?;
+        }
+    }
+    Ok(())
+}
+
+/// The Junos route-filter match type for `range`
+fn junos_match(range: &PrefixRange) -> String {
+    if range.is_exact() {
+        String::from("exact")
+    } else if range.min_len() == range.prefix().prefix_len() {
+        format!("upto /{}", range.max_len())
+    } else {
+        format!(
+            "prefix-length-range /{}-/{}",
+            range.min_len(),
+            range.max_len()
+        )
+    }
+}
+
+/// `range` in BIRD prefix set syntax, eg. `10.0.0.0/8{16,24}`
+fn bird_range(range: PrefixRange) -> String {
+    match range.is_exact() {
+        true => range.prefix().to_string(),
+        false => format!(
+            "{}{{{},{}}}",
+            range.prefix(),
+            range.min_len(),
+            range.max_len()
+        ),
+    }
+}
+
+/// BIRD prefix sets are single-family and can't be empty, so empty ones are
+/// omitted entirely.
+fn write_bird_set<W: Write, N: std::fmt::Display>(
+    w: &mut W,
+    name: &str,
+    nets: impl IntoIterator<Item = N>,
+) -> io::Result<()> {
+    let mut nets = nets.into_iter().peekable();
+    if nets.peek().is_none() {
+        return Ok(());
+    }
+    writeln!(w, "define {} = [", name)?;
+    while let Some(net) = nets.next() {
+        let sep = if nets.peek().is_some() { "," } else { "" };
+        writeln!(w, "    {}{}", net, sep)?;
+    }
+    writeln!(w, "];")
+}
+
+/// Firewalls that address sets can be written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Firewall {
+    /// An `nft -f` script defining interval sets
+    Nftables,
+    /// An `ipset restore` script defining `hash:net` sets
+    Ipset,
+    /// `iptables` / `ip6tables` commands dropping traffic from each prefix
+    Iptables,
+    /// A pf table file, for `pfctl -T replace -f`
+    Pf,
+}
+
+/// How firewall sets are named.
+#[derive(Clone, Debug)]
+pub struct FirewallOptions {
+    /// Set or chain name. Where a format needs one set per address family,
+    /// they are suffixed `_v4` and `_v6`.
+    pub name: String,
+    /// nftables family and table holding the sets, eg. `inet filter`
+    pub nft_table: String,
+}
+
+impl Default for FirewallOptions {
+    fn default() -> Self {
+        FirewallOptions {
+            name: String::from("AGGREGATE"),
+            nft_table: String::from("inet filter"),
+        }
+    }
+}
+
+/// Write a script that replaces the contents of firewall sets with exactly
+/// the given prefixes.
+pub fn write_firewall<W: Write>(
+    w: &mut W,
+    firewall: Firewall,
+    opts: &FirewallOptions,
+    v4: impl IntoIterator<Item = Ipv4Net>,
+    v6: impl IntoIterator<Item = Ipv6Net>,
+) -> io::Result<()> {
+    let name = &opts.name;
+    match firewall {
+        Firewall::Nftables => {
+            let table = &opts.nft_table;
+            // nft -f applies the whole script atomically, so flushing first
+            // doesn't leave a window with an empty set
+            for (set, kind) in [
+                (format!("{}_v4", name), "ipv4_addr"),
+                (format!("{}_v6", name), "ipv6_addr"),
+            ] {
+                writeln!(
+                    w,
+                    "add set {} {} {{ type {}; flags interval; }}",
+                    table, set, kind
+                )?;
+                writeln!(w, "flush set {} {}", table, set)?;
+            }
+            write_nft_elements(w, "add", table, &format!("{}_v4", name), v4)?;
+            write_nft_elements(w, "add", table, &format!("{}_v6", name), v6)?;
+        }
+        Firewall::Ipset => {
+            let v4: Vec<Ipv4Net> = v4.into_iter().flat_map(ipset_nets).collect();
+            let v6: Vec<Ipv6Net> = v6.into_iter().flat_map(ipset_nets).collect();
+            write_ipset(w, &format!("{}_v4", name), "inet", &v4)?;
+            write_ipset(w, &format!("{}_v6", name), "inet6", &v6)?;
+        }
+        Firewall::Iptables => {
+            for net in v4 {
+                writeln!(w, "iptables -A {} -s {} -j DROP", name, net)?;
+            }
+            for net in v6 {
+                writeln!(w, "ip6tables -A {} -s {} -j DROP", name, net)?;
+            }
+        }
+        Firewall::Pf => {
+            writeln!(
+                w,
+                "# pf table <{}>, load with: pfctl -t {} -T replace -f FILE",
+                name, name
+            )?;
+            for net in v4 {
+                writeln!(w, "{}", net)?;
+            }
+            for net in v6 {
+                writeln!(w, "{}", net)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write a script that updates firewall sets in place by deleting `removed`
+/// and adding `added`.
+///
+/// nft interval sets reject overlapping elements, so `removed` is deleted
+/// before `added` is added. ipset hash:net sets allow overlaps, so there
+/// `added` comes first.
+///
+/// Only [`Firewall::Nftables`] and [`Firewall::Ipset`] support this.
+pub fn write_firewall_update<W: Write>(
+    w: &mut W,
+    firewall: Firewall,
+    opts: &FirewallOptions,
+    removed: &IpBothRange,
+    added: &IpBothRange,
+) -> io::Result<()> {
+    let (v4_set, v6_set) = (format!("{}_v4", opts.name), format!("{}_v6", opts.name));
+    match firewall {
+        Firewall::Nftables => {
+            let table = &opts.nft_table;
+            write_nft_elements(w, "delete", table, &v4_set, removed.v4_iter())?;
+            write_nft_elements(w, "delete", table, &v6_set, removed.v6_iter())?;
+            write_nft_elements(w, "add", table, &v4_set, added.v4_iter())?;
+            write_nft_elements(w, "add", table, &v6_set, added.v6_iter())?;
+        }
+        Firewall::Ipset => {
+            for (verb, nets) in [("add", added), ("del", removed)] {
+                for net in nets.v4_iter().flat_map(|n| ipset_nets(*n)) {
+                    writeln!(w, "{} {} {}", verb, v4_set, net)?;
+                }
+                for net in nets.v6_iter().flat_map(|n| ipset_nets(*n)) {
+                    writeln!(w, "{} {} {}", verb, v6_set, net)?;
+                }
+            }
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "incremental updates are only supported for nftables and ipset",
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Write an nft `add element` or `delete element` command, omitted if there
+/// are no elements since nft rejects an empty list.
+fn write_nft_elements<W: Write, N: std::fmt::Display>(
+    w: &mut W,
+    verb: &str,
+    table: &str,
+    set: &str,
+    nets: impl IntoIterator<Item = N>,
+) -> io::Result<()> {
+    let mut nets = nets.into_iter().peekable();
+    if nets.peek().is_none() {
+        return Ok(());
+    }
+    writeln!(w, "{} element {} {} {{", verb, table, set)?;
+    while let Some(net) = nets.next() {
+        let sep = if nets.peek().is_some() { "," } else { "" };
+        writeln!(w, "    {}{}", net, sep)?;
+    }
+    writeln!(w, "}}")
+}
+
+/// ipset's hash:net type rejects zero-length prefixes, so split those in half.
+fn ipset_nets<P: Prefix>(net: P) -> Vec<P> {
+    match net.len() {
+        0 => vec![P::from_bits(0, 1), P::from_bits(1 << (P::BITS - 1), 1)],
+        _ => vec![net],
+    }
+}
+
+fn write_ipset<W: Write, N: std::fmt::Display>(
+    w: &mut W,
+    set: &str,
+    family: &str,
+    nets: &[N],
+) -> io::Result<()> {
+    // The default maxelem of 65536 is too small for many blocklists
+    let maxelem = nets.len().max(65536);
+    writeln!(
+        w,
+        "create {} hash:net family {} maxelem {} -exist",
+        set, family, maxelem
+    )?;
+    writeln!(w, "flush {}", set)?;
+    for net in nets {
+        writeln!(w, "add {} {}", set, net)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nets(nets: &[&str]) -> Vec<IpNet> {
+        nets.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn v4(nets: &[&str]) -> Vec<Ipv4Net> {
+        nets.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn v6(nets: &[&str]) -> Vec<Ipv6Net> {
+        nets.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn prefix_list(router: Router, opts: &PrefixListOptions) -> String {
+        let mut out = Vec::new();
+        write_prefix_list(
+            &mut out,
+            router,
+            opts,
+            v4(&["192.0.2.0/24", "198.51.100.0/24"]),
+            v6(&["2001:db8::/32"]),
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn prefix_list_ios() {
+        let opts = PrefixListOptions {
+            name: String::from("PL"),
+            sequence: NonZeroU32::new(5),
+        };
+        assert_eq!(
+            prefix_list(Router::CiscoIos, &opts),
+            "ip prefix-list PL seq 5 permit 192.0.2.0/24\n\
+             ip prefix-list PL seq 10 permit 198.51.100.0/24\n\
+             ipv6 prefix-list PL seq 5 permit 2001:db8::/32\n"
+        );
+        assert_eq!(
+            prefix_list(Router::Frr, &PrefixListOptions::default()),
+            "ip prefix-list AGGREGATE permit 192.0.2.0/24\n\
+             ip prefix-list AGGREGATE permit 198.51.100.0/24\n\
+             ipv6 prefix-list AGGREGATE permit 2001:db8::/32\n"
+        );
+    }
+
+    #[test]
+    fn prefix_list_xr() {
+        assert_eq!(
+            prefix_list(Router::CiscoXr, &PrefixListOptions::default()),
+            "prefix-set AGGREGATE\n  192.0.2.0/24,\n  198.51.100.0/24,\n  2001:db8::/32\nend-set\n"
+        );
+    }
+
+    #[test]
+    fn prefix_list_junos() {
+        assert_eq!(
+            prefix_list(Router::Junos, &PrefixListOptions::default()),
+            "set policy-options prefix-list AGGREGATE 192.0.2.0/24\n\
+             set policy-options prefix-list AGGREGATE 198.51.100.0/24\n\
+             set policy-options prefix-list AGGREGATE 2001:db8::/32\n"
+        );
+    }
+
+    #[test]
+    fn prefix_list_eos() {
+        let opts = PrefixListOptions {
+            name: String::from("PL"),
+            sequence: NonZeroU32::new(10),
+        };
+        assert_eq!(
+            prefix_list(Router::AristaEos, &opts),
+            "ip prefix-list PL\n   seq 10 permit 192.0.2.0/24\n   seq 20 permit 198.51.100.0/24\n\
+             ipv6 prefix-list PL\n   seq 10 permit 2001:db8::/32\n"
+        );
+    }
+
+    #[test]
+    fn prefix_list_bird() {
+        assert_eq!(
+            prefix_list(Router::Bird, &PrefixListOptions::default()),
+            "define AGGREGATE_v4 = [\n    192.0.2.0/24,\n    198.51.100.0/24\n];\n\
+             define AGGREGATE_v6 = [\n    2001:db8::/32\n];\n"
+        );
+        let mut out = Vec::new();
+        write_prefix_list(
+            &mut out,
+            Router::Bird,
+            &PrefixListOptions::default(),
+            v4(&[]),
+            v6(&["2001:db8::/32"]),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "define AGGREGATE_v6 = [\n    2001:db8::/32\n];\n"
+        );
+    }
+
+    fn prefix_range_list(router: Router) -> String {
+        let range = |s: &str, min_len, max_len| {
+            PrefixRange::new(s.parse().unwrap(), min_len, max_len).unwrap()
+        };
+        let mut out = Vec::new();
+        write_prefix_range_list(
+            &mut out,
+            router,
+            &PrefixListOptions::default(),
+            [range("10.0.0.0/8", 8, 24), range("192.0.2.0/24", 24, 24)],
+            [range("2001:db8::/32", 48, 64)],
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn prefix_range_list_ios() {
+        assert_eq!(
+            prefix_range_list(Router::CiscoIos),
+            "ip prefix-list AGGREGATE permit 10.0.0.0/8 le 24\n\
+             ip prefix-list AGGREGATE permit 192.0.2.0/24\n\
+             ipv6 prefix-list AGGREGATE permit 2001:db8::/32 ge 48 le 64\n"
+        );
+    }
+
+    #[test]
+    fn prefix_range_list_junos() {
+        assert_eq!(
+            prefix_range_list(Router::Junos),
+            "set policy-options route-filter-list AGGREGATE 10.0.0.0/8 upto /24\n\
+             set policy-options route-filter-list AGGREGATE 192.0.2.0/24 exact\n\
+             set policy-options route-filter-list AGGREGATE 2001:db8::/32 prefix-length-range /48-/64\n"
+        );
+    }
+
+    #[test]
+    fn prefix_range_list_bird() {
+        assert_eq!(
+            prefix_range_list(Router::Bird),
+            "define AGGREGATE_v4 = [\n    10.0.0.0/8{8,24},\n    192.0.2.0/24\n];\n\
+             define AGGREGATE_v6 = [\n    2001:db8::/32{48,64}\n];\n"
+        );
+    }
+
+    fn firewall(firewall: Firewall, v4_nets: &[&str], v6_nets: &[&str]) -> String {
+        let opts = FirewallOptions {
+            name: String::from("BLOCK"),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_firewall(&mut out, firewall, &opts, v4(v4_nets), v6(v6_nets)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn firewall_nft() {
+        assert_eq!(
+            firewall(
+                Firewall::Nftables,
+                &["192.0.2.0/24", "198.51.100.0/24"],
+                &[]
+            ),
+            "add set inet filter BLOCK_v4 { type ipv4_addr; flags interval; }\n\
+             flush set inet filter BLOCK_v4\n\
+             add set inet filter BLOCK_v6 { type ipv6_addr; flags interval; }\n\
+             flush set inet filter BLOCK_v6\n\
+             add element inet filter BLOCK_v4 {\n    192.0.2.0/24,\n    198.51.100.0/24\n}\n"
+        );
+    }
+
+    #[test]
+    fn firewall_ipset() {
+        assert_eq!(
+            firewall(Firewall::Ipset, &["192.0.2.0/24"], &["::/0"]),
+            "create BLOCK_v4 hash:net family inet maxelem 65536 -exist\n\
+             flush BLOCK_v4\n\
+             add BLOCK_v4 192.0.2.0/24\n\
+             create BLOCK_v6 hash:net family inet6 maxelem 65536 -exist\n\
+             flush BLOCK_v6\n\
+             add BLOCK_v6 ::/1\n\
+             add BLOCK_v6 8000::/1\n"
+        );
+    }
+
+    #[test]
+    fn firewall_iptables() {
+        assert_eq!(
+            firewall(Firewall::Iptables, &["192.0.2.0/24"], &["2001:db8::/32"]),
+            "iptables -A BLOCK -s 192.0.2.0/24 -j DROP\n\
+             ip6tables -A BLOCK -s 2001:db8::/32 -j DROP\n"
+        );
+    }
+
+    #[test]
+    fn firewall_pf() {
+        assert_eq!(
+            firewall(Firewall::Pf, &["192.0.2.0/24"], &["2001:db8::/32"]),
+            "# pf table <BLOCK>, load with: pfctl -t BLOCK -T replace -f FILE\n\
+             192.0.2.0/24\n\
+             2001:db8::/32\n"
+        );
+    }
+
+    #[test]
+    fn firewall_update() {
+        let removed: IpBothRange = ["192.0.2.0/25", "::/0"]
+            .iter()
+            .map(|s| s.parse::<crate::IpOrNet>().unwrap())
+            .collect();
+        let added: IpBothRange = ["192.0.2.0/24"]
+            .iter()
+            .map(|s| s.parse::<crate::IpOrNet>().unwrap())
+            .collect();
+        let opts = FirewallOptions {
+            name: String::from("BLOCK"),
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_firewall_update(&mut out, Firewall::Nftables, &opts, &removed, &added).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "delete element inet filter BLOCK_v4 {\n    192.0.2.0/25\n}\n\
+             delete element inet filter BLOCK_v6 {\n    ::/0\n}\n\
+             add element inet filter BLOCK_v4 {\n    192.0.2.0/24\n}\n"
+        );
+
+        let mut out = Vec::new();
+        write_firewall_update(&mut out, Firewall::Ipset, &opts, &removed, &added).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "add BLOCK_v4 192.0.2.0/24\n\
+             del BLOCK_v4 192.0.2.0/25\n\
+             del BLOCK_v6 ::/1\n\
+             del BLOCK_v6 8000::/1\n"
+        );
+
+        let mut out = Vec::new();
+        assert!(write_firewall_update(&mut out, Firewall::Pf, &opts, &removed, &added).is_err());
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut out = Vec::new();
+        write_json_lines(&mut out, nets(&["192.0.2.0/24", "2001:db8::/32"])).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"{"network":"192.0.2.0/24","family":"ipv4","prefix_length":24,"first":"192.0.2.0","last":"192.0.2.255","addresses":"256"}"#,
+                "\n",
+                r#"{"network":"2001:db8::/32","family":"ipv6","prefix_length":32,"first":"2001:db8::","last":"2001:db8:ffff:ffff:ffff:ffff:ffff:ffff","addresses":"79228162514264337593543950336"}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn json_array() {
+        let mut out = Vec::new();
+        write_json(&mut out, nets(&["0.0.0.0/0", "::/0"])).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "[\n  ",
+                r#"{"network":"0.0.0.0/0","family":"ipv4","prefix_length":0,"first":"0.0.0.0","last":"255.255.255.255","addresses":"4294967296"}"#,
+                ",\n  ",
+                r#"{"network":"::/0","family":"ipv6","prefix_length":0,"first":"::","last":"ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff","addresses":"340282366920938463463374607431768211456"}"#,
+                "\n]\n"
+            )
+        );
+    }
+
+    #[test]
+    fn json_empty() {
+        let mut out = Vec::new();
+        write_json(&mut out, nets(&[])).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[\n]\n");
+    }
+}
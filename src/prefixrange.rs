@@ -0,0 +1,400 @@
+//! Prefix-list entries with `ge`/`le` length ranges, and compressing a set of
+//! exact prefixes into them.
+
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+
+use crate::ranges::{self, Prefix, Range};
+use crate::{IpOrNet, NetParseError, PrefixlenPair};
+
+/// A prefix-list entry matching every prefix within `prefix` whose length is
+/// from `min_len` to `max_len` inclusive, as written `prefix ge min_len le
+/// max_len` on most routers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PrefixRange {
+    prefix: IpNet,
+    min_len: u8,
+    max_len: u8,
+}
+
+impl PrefixRange {
+    pub fn new(prefix: IpNet, min_len: u8, max_len: u8) -> Result<Self, NetParseError> {
+        if min_len < prefix.prefix_len() || min_len > max_len || max_len > prefix.max_prefix_len() {
+            return Err(NetParseError {
+                msg: "Invalid prefix length range",
+            });
+        }
+        Ok(PrefixRange {
+            prefix,
+            min_len,
+            max_len,
+        })
+    }
+    pub fn prefix(&self) -> IpNet {
+        self.prefix
+    }
+    pub fn min_len(&self) -> u8 {
+        self.min_len
+    }
+    pub fn max_len(&self) -> u8 {
+        self.max_len
+    }
+    /// The same entry with the host bits of `prefix` cleared.
+    pub fn trunc(&self) -> PrefixRange {
+        PrefixRange {
+            prefix: self.prefix.trunc(),
+            ..*self
+        }
+    }
+    /// Whether only `prefix` itself is matched.
+    pub fn is_exact(&self) -> bool {
+        self.min_len == self.prefix.prefix_len() && self.max_len == self.prefix.prefix_len()
+    }
+    /// Every prefix matched, shortest first, without collecting them.
+    pub fn prefixes(&self) -> impl Iterator<Item = IpNet> + '_ {
+        (self.min_len..=self.max_len).flat_map(|len| self.prefix.subnets(len).unwrap())
+    }
+    /// Each prefix matched replaced with its covering prefix of the length
+    /// given in `len` for this address family, if longer, as with
+    /// [`IpOrNet::widen`].
+    pub fn widen(&self, len: &PrefixlenPair) -> PrefixRange {
+        let len = match self.prefix {
+            IpNet::V4(_) => len.v4.min(32),
+            IpNet::V6(_) => len.v6.min(128),
+        };
+        if self.max_len <= len {
+            return *self;
+        }
+        match self.prefix.prefix_len() < len {
+            true => PrefixRange {
+                min_len: self.min_len.min(len),
+                max_len: len,
+                ..*self
+            },
+            false => IpNet::new(self.prefix.network(), len)
+                .unwrap()
+                .trunc()
+                .into(),
+        }
+    }
+    /// Number of prefixes matched, or `None` if it doesn't fit in a `u128`.
+    pub fn count(&self) -> Option<u128> {
+        (self.min_len..=self.max_len)
+            .map(|len| 1u128.checked_shl((len - self.prefix.prefix_len()).into()))
+            .try_fold(0u128, |total, count| total.checked_add(count?))
+    }
+    /// The fewest entries, near enough, matching exactly the prefixes matched
+    /// by any of `ranges`, IPv4 first, in address order.
+    pub fn compress(ranges: &[PrefixRange]) -> Vec<PrefixRange> {
+        let mut v4: Vec<(Ipv4Net, u8, u8)> = Vec::new();
+        let mut v6: Vec<(Ipv6Net, u8, u8)> = Vec::new();
+        for range in ranges {
+            match range.prefix.trunc() {
+                IpNet::V4(net) => v4.push((net, range.min_len, range.max_len)),
+                IpNet::V6(net) => v6.push((net, range.min_len, range.max_len)),
+            }
+        }
+        let mut out = compress(&v4);
+        out.extend(compress(&v6));
+        out
+    }
+}
+
+impl From<IpNet> for PrefixRange {
+    fn from(prefix: IpNet) -> Self {
+        PrefixRange {
+            prefix,
+            min_len: prefix.prefix_len(),
+            max_len: prefix.prefix_len(),
+        }
+    }
+}
+
+/// Cisco style, eg. `10.0.0.0/8 le 24`, omitting qualifiers that match the
+/// default: `ge` defaults to the prefix length, and `le` to the full address
+/// length when `ge` is given.
+impl Display for PrefixRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let len = self.prefix.prefix_len();
+        let max = self.prefix.max_prefix_len();
+        write!(f, "{}", self.prefix)?;
+        match (self.min_len == len, self.max_len == max) {
+            (true, _) if self.max_len == len => Ok(()),
+            (true, _) => write!(f, " le {}", self.max_len),
+            (false, true) => write!(f, " ge {}", self.min_len),
+            (false, false) => write!(f, " ge {} le {}", self.min_len, self.max_len),
+        }
+    }
+}
+
+fn parse_error(msg: &'static str) -> Box<dyn Error> {
+    Box::new(NetParseError { msg })
+}
+
+fn parse_len(s: Option<&str>) -> Result<u8, Box<dyn Error>> {
+    let s = s.ok_or_else(|| parse_error("Missing prefix length"))?;
+    Ok(s.strip_prefix('/').unwrap_or(s).parse()?)
+}
+
+/// Accepted formats, with an optional trailing `;`:
+///   Cisco style   - `10.0.0.0/8 ge 16 le 24`, `10.0.0.0/8 le 24`, `10.0.0.0/8 eq 24`
+///   Junos style   - `10.0.0.0/8 upto /24`, `10.0.0.0/8 prefix-length-range /16-/24`,
+///                   `10.0.0.0/8 exact`, `10.0.0.0/8 orlonger`, `10.0.0.0/8 longer`
+///
+/// As with [`IpOrNet`], the prefix may have host bits set.
+impl FromStr for PrefixRange {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.trim_end_matches(';').split_ascii_whitespace();
+        let prefix = words
+            .next()
+            .ok_or_else(|| parse_error("Missing prefix"))?
+            .parse::<IpOrNet>()?
+            .net();
+        let len = prefix.prefix_len();
+        let max = prefix.max_prefix_len();
+        let (mut ge, mut le) = (None, None);
+        while let Some(word) = words.next() {
+            match word {
+                "ge" if ge.is_none() => ge = Some(parse_len(words.next())?),
+                "le" if le.is_none() => le = Some(parse_len(words.next())?),
+                "eq" | "upto" | "prefix-length-range" | "exact" | "orlonger" | "longer"
+                    if ge.is_none() && le.is_none() =>
+                {
+                    let (min_len, max_len) = match word {
+                        "eq" => {
+                            let eq = parse_len(words.next())?;
+                            (eq, eq)
+                        }
+                        "upto" => (len, parse_len(words.next())?),
+                        "prefix-length-range" => {
+                            let range = words.next().unwrap_or_default();
+                            let (min_len, max_len) = range
+                                .split_once('-')
+                                .ok_or_else(|| parse_error("Length range must be /min-/max"))?;
+                            (parse_len(Some(min_len))?, parse_len(Some(max_len))?)
+                        }
+                        "exact" => (len, len),
+                        "orlonger" => (len, max),
+                        _ => (
+                            len.checked_add(1)
+                                .ok_or_else(|| parse_error("No longer prefixes"))?,
+                            max,
+                        ),
+                    };
+                    (ge, le) = (Some(min_len), Some(max_len));
+                }
+                // A `;` separated from the last word by whitespace
+                ";" => (),
+                _ => return Err(parse_error("Unknown prefix length qualifier")),
+            }
+        }
+        // Cisco semantics: `ge` alone allows up to the full address length
+        let min_len = ge.unwrap_or(len);
+        let max_len = le.unwrap_or(if ge.is_some() { max } else { len });
+        Ok(PrefixRange::new(prefix, min_len, max_len)?)
+    }
+}
+
+/// Whether `range` lies entirely within one of `ranges`, which must be sorted
+/// and disjoint.
+fn contains(ranges: &[Range], (first, last): Range) -> bool {
+    let i = ranges.partition_point(|r| r.1 < first);
+    ranges.get(i).is_some_and(|r| r.0 <= first && r.1 >= last)
+}
+
+/// Entries matching exactly the prefixes matched by any of `entries`, given
+/// as `(prefix, min_len, max_len)`, in address order.
+///
+/// The prefixes of each length that are matched are all those within some
+/// span of addresses, so the entries are worked out from those spans without
+/// listing the prefixes. An entry can only be used where its prefix is within
+/// the span of each length in its range. Working from the shortest length up,
+/// each largest prefix within the span of a length that isn't yet matched at
+/// that length is given an entry, widened to as many lengths as possible,
+/// which finds the minimal set of entries in all but contrived cases.
+pub(crate) fn compress<P: Prefix + Into<IpNet>>(entries: &[(P, u8, u8)]) -> Vec<PrefixRange> {
+    // Addresses whose prefixes of each length are matched, indexed by length
+    let mut spans: Vec<Vec<Range>> = vec![Vec::new(); usize::from(P::BITS) + 1];
+    for (net, min_len, max_len) in entries {
+        for len in (*min_len).max(net.len())..=(*max_len).min(P::BITS) {
+            spans[usize::from(len)].push((net.first(), net.last()));
+        }
+    }
+    let spans: Vec<Vec<Range>> = spans.into_iter().map(ranges::merge).collect();
+
+    // Addresses whose prefixes of each length are matched by earlier entries
+    let mut matched: Vec<Vec<Range>> = vec![Vec::new(); spans.len()];
+    let mut out = Vec::new();
+    for len in 0..=P::BITS {
+        let done = ranges::merge(std::mem::take(&mut matched[usize::from(len)]));
+        for net in ranges::to_prefixes::<P>(&spans[usize::from(len)]) {
+            let range = (net.first(), net.last());
+            if contains(&done, range) {
+                continue;
+            }
+            let mut min_len = len;
+            while min_len > net.len() && contains(&spans[usize::from(min_len - 1)], range) {
+                min_len -= 1;
+            }
+            let mut max_len = len;
+            while max_len < P::BITS && contains(&spans[usize::from(max_len + 1)], range) {
+                max_len += 1;
+            }
+            for level in len + 1..=max_len {
+                matched[usize::from(level)].push(range);
+            }
+            out.push(PrefixRange {
+                prefix: net.into(),
+                min_len,
+                max_len,
+            });
+        }
+    }
+    out.sort_unstable();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compressed(nets: &[&str]) -> Vec<String> {
+        let ranges: Vec<PrefixRange> = nets.iter().map(|s| s.parse().unwrap()).collect();
+        PrefixRange::compress(&ranges)
+            .iter()
+            .map(PrefixRange::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn display_qualifiers() {
+        let range = |s: &str, min_len, max_len| {
+            PrefixRange::new(s.parse().unwrap(), min_len, max_len).unwrap()
+        };
+        assert_eq!(range("10.0.0.0/8", 8, 8).to_string(), "10.0.0.0/8");
+        assert_eq!(range("10.0.0.0/8", 8, 24).to_string(), "10.0.0.0/8 le 24");
+        assert_eq!(
+            range("10.0.0.0/8", 16, 24).to_string(),
+            "10.0.0.0/8 ge 16 le 24"
+        );
+        assert_eq!(range("10.0.0.0/8", 16, 32).to_string(), "10.0.0.0/8 ge 16");
+        assert_eq!(
+            range("2001:db8::/32", 48, 128).to_string(),
+            "2001:db8::/32 ge 48"
+        );
+    }
+
+    #[test]
+    fn parse_qualifiers() {
+        let parsed = |s: &str| {
+            let range: PrefixRange = s.parse().unwrap();
+            (range.prefix.to_string(), range.min_len, range.max_len)
+        };
+        let net = String::from("10.0.0.0/8");
+        assert_eq!(parsed("10.0.0.0/8"), (net.clone(), 8, 8));
+        assert_eq!(parsed("10.0.0.0/8 le 24"), (net.clone(), 8, 24));
+        assert_eq!(parsed("10.0.0.0/8 ge 16"), (net.clone(), 16, 32));
+        assert_eq!(parsed("10.0.0.0/8 le 24 ge 16"), (net.clone(), 16, 24));
+        assert_eq!(parsed("10.0.0.0/8 eq 24"), (net.clone(), 24, 24));
+        assert_eq!(parsed("10.0.0.0/8 upto /24;"), (net.clone(), 8, 24));
+        assert_eq!(
+            parsed("10.0.0.0/8 prefix-length-range /16-/24 ;"),
+            (net.clone(), 16, 24)
+        );
+        assert_eq!(parsed("10.0.0.0/8 exact"), (net.clone(), 8, 8));
+        assert_eq!(parsed("10.0.0.0/8 orlonger"), (net.clone(), 8, 32));
+        assert_eq!(parsed("10.0.0.0/8 longer"), (net, 9, 32));
+        assert_eq!(
+            parsed("2001:db8::/32 ge 48"),
+            (String::from("2001:db8::/32"), 48, 128)
+        );
+    }
+
+    #[test]
+    fn parse_invalid_qualifiers() {
+        for s in [
+            "10.0.0.0/8 le",
+            "10.0.0.0/8 le 4",
+            "10.0.0.0/8 ge 24 le 16",
+            "10.0.0.0/8 le 33",
+            "10.0.0.0/8 le 24 le 24",
+            "10.0.0.0/8 exact le 24",
+            "10.0.0.0/8 prefix-length-range /16",
+            "10.0.0.0/8 upto",
+            "10.0.0.0/8 within 24",
+            "192.0.2.1/32 longer",
+        ] {
+            assert!(s.parse::<PrefixRange>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn new_checks_lengths() {
+        let net: IpNet = "10.0.0.0/8".parse().unwrap();
+        assert!(PrefixRange::new(net, 8, 32).is_ok());
+        assert!(PrefixRange::new(net, 4, 24).is_err());
+        assert!(PrefixRange::new(net, 24, 16).is_err());
+        assert!(PrefixRange::new(net, 8, 33).is_err());
+    }
+
+    #[test]
+    fn expand_prefixes() {
+        let range: PrefixRange = "10.0.0.0/23 le 24".parse().unwrap();
+        let nets: Vec<String> = range.prefixes().map(|n| n.to_string()).collect();
+        assert_eq!(nets, ["10.0.0.0/23", "10.0.0.0/24", "10.0.1.0/24"]);
+        assert_eq!(range.count(), Some(3));
+        let all: PrefixRange = "::/0 orlonger".parse().unwrap();
+        assert_eq!(all.count(), None);
+    }
+
+    #[test]
+    fn full_levels() {
+        assert_eq!(
+            compressed(&["10.0.0.0/23", "10.0.0.0/24", "10.0.1.0/24", "192.0.2.0/24"]),
+            ["10.0.0.0/23 le 24", "192.0.2.0/24"]
+        );
+        assert_eq!(
+            compressed(&["10.0.0.0/24", "10.0.1.0/24", "10.0.2.0/24", "10.0.3.0/24"]),
+            ["10.0.0.0/22 ge 24 le 24"]
+        );
+    }
+
+    #[test]
+    fn incomplete_level_kept_exact() {
+        assert_eq!(
+            compressed(&["10.0.0.0/24", "10.0.1.0/24", "10.0.2.0/24"]),
+            ["10.0.0.0/23 ge 24 le 24", "10.0.2.0/24"]
+        );
+    }
+
+    #[test]
+    fn ranges_not_expanded() {
+        assert_eq!(compressed(&["0.0.0.0/0 le 32"]), ["0.0.0.0/0 le 32"]);
+        assert_eq!(
+            compressed(&["10.0.0.0/8 le 32", "10.0.0.0/16", "11.0.0.0/8 le 32"]),
+            ["10.0.0.0/7 ge 8"]
+        );
+    }
+
+    #[test]
+    fn widen_ranges() {
+        let widened = |s: &str| {
+            let range: PrefixRange = s.parse().unwrap();
+            range.widen(&PrefixlenPair { v4: 24, v6: 48 }).to_string()
+        };
+        assert_eq!(widened("10.0.0.0/8 le 32"), "10.0.0.0/8 le 24");
+        assert_eq!(widened("10.0.0.0/8 ge 28"), "10.0.0.0/8 ge 24 le 24");
+        assert_eq!(widened("10.0.0.0/8 le 16"), "10.0.0.0/8 le 16");
+        assert_eq!(widened("192.0.2.128/25 le 32"), "192.0.2.0/24");
+    }
+
+    #[test]
+    fn nested_entries() {
+        assert_eq!(
+            compressed(&["10.0.0.0/24", "10.0.1.0/24", "10.0.0.0/25", "10.0.0.128/25"]),
+            ["10.0.0.0/23 ge 24 le 24", "10.0.0.0/24 le 25"]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Address-interval helpers shared by the set operations on `IpBothRange`.
+//!
+//! Both address families are handled by widening addresses to `u128` and
+//! working on inclusive `(first, last)` intervals, which avoids overflow at the
+//! top of the IPv6 address space.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use ipnet::{Ipv4Net, Ipv6Net};
+
+/// An inclusive range of addresses, widened to `u128`.
+pub(crate) type Range = (u128, u128);
+
+/// Operations shared by `Ipv4Net` and `Ipv6Net` so that set operations only
+/// need to be written once.
+pub(crate) trait Prefix: Copy + Ord {
+    const BITS: u8;
+    fn first(&self) -> u128;
+    fn last(&self) -> u128;
+    fn len(&self) -> u8;
+    /// Construct from a network address and prefix length. `addr` must not
+    /// have host bits set.
+    fn from_bits(addr: u128, len: u8) -> Self;
+}
+
+impl Prefix for Ipv4Net {
+    const BITS: u8 = 32;
+    fn first(&self) -> u128 {
+        u32::from(self.network()).into()
+    }
+    fn last(&self) -> u128 {
+        u32::from(self.broadcast()).into()
+    }
+    fn len(&self) -> u8 {
+        self.prefix_len()
+    }
+    fn from_bits(addr: u128, len: u8) -> Self {
+        Ipv4Net::new(Ipv4Addr::from(addr as u32), len).unwrap()
+    }
+}
+
+impl Prefix for Ipv6Net {
+    const BITS: u8 = 128;
+    fn first(&self) -> u128 {
+        self.network().into()
+    }
+    fn last(&self) -> u128 {
+        self.broadcast().into()
+    }
+    fn len(&self) -> u8 {
+        self.prefix_len()
+    }
+    fn from_bits(addr: u128, len: u8) -> Self {
+        Ipv6Net::new(Ipv6Addr::from(addr), len).unwrap()
+    }
+}
+
+/// The range of the prefix of length `len` containing `addr`.
+pub(crate) fn block<P: Prefix>(addr: u128, len: u8) -> Range {
+    let host_bits = u32::from(P::BITS - len);
+    let host_mask = u128::MAX.checked_shr(128 - host_bits).unwrap_or(0);
+    (addr & !host_mask, addr | host_mask)
+}
+
+/// Collapse prefixes, in any order, into sorted maximal runs of addresses.
+pub(crate) fn to_ranges<P: Prefix>(nets: &[P]) -> Vec<Range> {
+    merge(nets.iter().map(|n| (n.first(), n.last())).collect())
+}
+
+/// Collapse ranges, in any order, into sorted maximal runs of addresses.
+pub(crate) fn merge(mut spans: Vec<Range>) -> Vec<Range> {
+    spans.sort_unstable();
+    let mut out: Vec<Range> = Vec::with_capacity(spans.len());
+    for (first, last) in spans {
+        match out.last_mut() {
+            Some(prev) if prev.1 == u128::MAX || first <= prev.1 + 1 => {
+                prev.1 = prev.1.max(last);
+            }
+            _ => out.push((first, last)),
+        }
+    }
+    out
+}
+
+/// Append the minimal list of prefixes exactly covering `first..=last`.
+pub(crate) fn push_range<P: Prefix>(out: &mut Vec<P>, (mut first, last): Range) {
+    let bits = u32::from(P::BITS);
+    loop {
+        let span = last - first;
+        // Largest power-of-two block that fits in what is left of the range...
+        let fit = if span == u128::MAX {
+            128
+        } else {
+            127 - (span + 1).leading_zeros()
+        };
+        // ...and that `first` is aligned to.
+        let host_bits = fit.min(first.trailing_zeros()).min(bits);
+        out.push(P::from_bits(first, (bits - host_bits) as u8));
+        if host_bits == 128 {
+            return;
+        }
+        let next = first + ((1u128 << host_bits) - 1);
+        if next >= last {
+            return;
+        }
+        first = next + 1;
+    }
+}
+
+pub(crate) fn to_prefixes<P: Prefix>(ranges: &[Range]) -> Vec<P> {
+    let mut out = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        push_range(&mut out, *range);
+    }
+    out
+}
+
+/// Addresses in `a` that are not in `b`. Both must be sorted and disjoint, as
+/// returned by [`to_ranges`].
+pub(crate) fn difference(a: &[Range], b: &[Range]) -> Vec<Range> {
+    let mut out = Vec::new();
+    let mut j = 0;
+    for &(first, last) in a {
+        let mut cur = first;
+        // Skip exclusions entirely below this range
+        while j < b.len() && b[j].1 < cur {
+            j += 1;
+        }
+        let mut k = j;
+        let mut done = false;
+        while k < b.len() && b[k].0 <= last {
+            if b[k].0 > cur {
+                out.push((cur, b[k].0 - 1));
+            }
+            if b[k].1 >= last {
+                done = true;
+                break;
+            }
+            cur = b[k].1 + 1;
+            k += 1;
+        }
+        if !done {
+            out.push((cur, last));
+        }
+    }
+    out
+}
+
+/// Addresses in both `a` and `b`. Both must be sorted and disjoint, as
+/// returned by [`to_ranges`].
+pub(crate) fn intersection(a: &[Range], b: &[Range]) -> Vec<Range> {
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let first = a[i].0.max(b[j].0);
+        let last = a[i].1.min(b[j].1);
+        if first <= last {
+            out.push((first, last));
+        }
+        // Whichever range ends first can't overlap anything further along
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_to_prefixes_v4() {
+        let mut out: Vec<Ipv4Net> = Vec::new();
+        push_range(&mut out, (0xc0000201, 0xc0000206));
+        let expect: Vec<Ipv4Net> = [
+            "192.0.2.1/32",
+            "192.0.2.2/31",
+            "192.0.2.4/31",
+            "192.0.2.6/32",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        assert_eq!(out, expect);
+    }
+
+    #[test]
+    fn range_to_prefixes_full() {
+        let mut v4: Vec<Ipv4Net> = Vec::new();
+        push_range(&mut v4, (0, u32::MAX.into()));
+        assert_eq!(v4, vec!["0.0.0.0/0".parse().unwrap()]);
+        let mut v6: Vec<Ipv6Net> = Vec::new();
+        push_range(&mut v6, (0, u128::MAX));
+        assert_eq!(v6, vec!["::/0".parse().unwrap()]);
+    }
+
+    #[test]
+    fn range_to_prefixes_all_but_last() {
+        let mut out: Vec<Ipv4Net> = Vec::new();
+        push_range(&mut out, (0, u32::MAX as u128 - 1));
+        assert_eq!(out.len(), 32);
+        assert_eq!(out[0], "0.0.0.0/1".parse().unwrap());
+        assert_eq!(out[31], "255.255.255.254/32".parse().unwrap());
+    }
+
+    #[test]
+    fn merge_adjacent_and_overlapping() {
+        let nets: Vec<Ipv4Net> = ["10.0.1.0/24", "10.0.0.0/24", "10.0.0.128/25", "10.0.3.0/24"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(
+            to_ranges(&nets),
+            vec![(0x0a000000, 0x0a0001ff), (0x0a000300, 0x0a0003ff)]
+        );
+    }
+
+    #[test]
+    fn difference_splits() {
+        assert_eq!(
+            difference(&[(0, 99)], &[(10, 19), (50, 200)]),
+            vec![(0, 9), (20, 49)]
+        );
+        assert_eq!(difference(&[(0, 9), (20, 29)], &[(0, 29)]), vec![]);
+        assert_eq!(
+            difference(&[(0, 9), (20, 29)], &[(5, 22)]),
+            vec![(0, 4), (23, 29)]
+        );
+    }
+
+    #[test]
+    fn intersection_partial() {
+        assert_eq!(
+            intersection(&[(0, 9), (20, 29)], &[(5, 22), (25, u128::MAX)]),
+            vec![(5, 9), (20, 22), (25, 29)]
+        );
+        assert_eq!(intersection(&[(0, 9)], &[(10, 19)]), vec![]);
+    }
+}
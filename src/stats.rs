@@ -0,0 +1,196 @@
+//! Summary statistics comparing prefixes before and after aggregation.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use crate::{ranges::Prefix, AddressCount, IpBothRange};
+
+/// Statistics for one address family.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FamilyStats {
+    pub input_prefixes: usize,
+    pub output_prefixes: usize,
+    /// Addresses covered by the output prefixes
+    pub addresses: AddressCount,
+    /// Number of input prefixes of each length
+    pub input_lengths: BTreeMap<u8, usize>,
+    /// Number of output prefixes of each length
+    pub output_lengths: BTreeMap<u8, usize>,
+}
+
+impl FamilyStats {
+    fn new<'a, P: Prefix + 'a>(
+        input: impl Iterator<Item = &'a P>,
+        output: impl Iterator<Item = &'a P>,
+        addresses: AddressCount,
+    ) -> Self {
+        let mut stats = FamilyStats {
+            addresses,
+            ..Default::default()
+        };
+        for net in input {
+            stats.input_prefixes += 1;
+            *stats.input_lengths.entry(net.len()).or_default() += 1;
+        }
+        for net in output {
+            stats.output_prefixes += 1;
+            *stats.output_lengths.entry(net.len()).or_default() += 1;
+        }
+        stats
+    }
+    /// Input prefixes per output prefix, or `None` if there is no output.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.output_prefixes {
+            0 => None,
+            n => Some(self.input_prefixes as f64 / n as f64),
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.input_prefixes == 0 && self.output_prefixes == 0
+    }
+}
+
+/// Statistics for both address families.
+///
+/// The `Display` implementation gives a human-readable report, omitting any
+/// address family with neither input nor output prefixes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub v4: FamilyStats,
+    pub v6: FamilyStats,
+}
+
+impl Stats {
+    /// Compare the prefixes as read, `input`, with their aggregate, `output`.
+    pub fn new(input: &IpBothRange, output: &IpBothRange) -> Self {
+        Stats {
+            v4: FamilyStats::new(input.v4_iter(), output.v4_iter(), output.v4_address_count()),
+            v6: FamilyStats::new(input.v6_iter(), output.v6_iter(), output.v6_address_count()),
+        }
+    }
+}
+
+/// `count` expressed as a number of prefixes with `host_bits` host bits, exact
+/// when it is a whole number.
+fn equivalents(count: AddressCount, host_bits: u8) -> String {
+    let block = 1u128 << host_bits;
+    match count.get() {
+        Some(n) if n % block == 0 => (n >> host_bits).to_string(),
+        Some(n) => format!("{:.2}", n as f64 / block as f64),
+        None => (1u128 << (128 - host_bits)).to_string(),
+    }
+}
+
+fn write_family(
+    f: &mut std::fmt::Formatter<'_>,
+    family: &str,
+    stats: &FamilyStats,
+    unit_len: u8,
+    bits: u8,
+) -> std::fmt::Result {
+    writeln!(f, "{}:", family)?;
+    writeln!(
+        f,
+        "  prefixes:          {} in, {} out",
+        stats.input_prefixes, stats.output_prefixes
+    )?;
+    match stats.compression_ratio() {
+        Some(ratio) => writeln!(f, "  compression ratio: {:.2}:1", ratio)?,
+        None => writeln!(f, "  compression ratio: -")?,
+    }
+    writeln!(
+        f,
+        "  addresses:         {} ({} /{}s)",
+        stats.addresses,
+        equivalents(stats.addresses, bits - unit_len),
+        unit_len
+    )?;
+    writeln!(f, "  prefix lengths:")?;
+    writeln!(f, "    {:<5}{:>10}{:>10}", "len", "in", "out")?;
+    let lengths: BTreeSet<&u8> = (stats.input_lengths.keys())
+        .chain(stats.output_lengths.keys())
+        .collect();
+    for len in lengths {
+        writeln!(
+            f,
+            "    /{:<4}{:>10}{:>10}",
+            len,
+            stats.input_lengths.get(len).unwrap_or(&0),
+            stats.output_lengths.get(len).unwrap_or(&0)
+        )?;
+    }
+    Ok(())
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.v4.is_empty() {
+            write_family(f, "IPv4", &self.v4, 24, 32)?;
+        }
+        if !self.v6.is_empty() {
+            write_family(f, "IPv6", &self.v6, 48, 128)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IpOrNet;
+
+    fn both(nets: &[&str]) -> IpBothRange {
+        nets.iter().map(|s| s.parse::<IpOrNet>().unwrap()).collect()
+    }
+
+    #[test]
+    fn counts_and_lengths() {
+        let input = both(&[
+            "10.0.0.0/25",
+            "10.0.0.128/25",
+            "10.0.1.0/24",
+            "2001:db8::/64",
+        ]);
+        let mut output = input.clone();
+        output.simplify();
+        let stats = Stats::new(&input, &output);
+
+        assert_eq!(stats.v4.input_prefixes, 3);
+        assert_eq!(stats.v4.output_prefixes, 1);
+        assert_eq!(stats.v4.addresses.get(), Some(512));
+        assert_eq!(stats.v4.input_lengths, BTreeMap::from([(24, 1), (25, 2)]));
+        assert_eq!(stats.v4.output_lengths, BTreeMap::from([(23, 1)]));
+        assert_eq!(stats.v4.compression_ratio(), Some(3.0));
+        assert_eq!(stats.v6.addresses.get(), Some(1 << 64));
+    }
+
+    #[test]
+    fn equivalents_exact_and_fractional() {
+        assert_eq!(equivalents(AddressCount::from_host_bits(9), 8), "2");
+        assert_eq!(equivalents(AddressCount::from_host_bits(7), 8), "0.50");
+        assert_eq!(
+            equivalents(AddressCount::from_host_bits(128), 80),
+            "281474976710656"
+        );
+    }
+
+    #[test]
+    fn report_skips_empty_family() {
+        let input = both(&["192.0.2.0/25", "192.0.2.128/25"]);
+        let mut output = input.clone();
+        output.simplify();
+        assert_eq!(
+            Stats::new(&input, &output).to_string(),
+            "IPv4:\n\
+             \x20 prefixes:          2 in, 1 out\n\
+             \x20 compression ratio: 2.00:1\n\
+             \x20 addresses:         256 (1 /24s)\n\
+             \x20 prefix lengths:\n\
+             \x20   len          in       out\n\
+             \x20   /24           0         1\n\
+             \x20   /25           2         0\n"
+        );
+    }
+}
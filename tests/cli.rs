@@ -1,4 +1,6 @@
 use assert_cmd::Command;
+use assert_fs::prelude::*;
+use assert_fs::NamedTempFile;
 use glob::glob;
 use predicates::prelude::*;
 use predicates::reflection::PredicateReflection;
@@ -14,7 +16,7 @@ struct SortedEquals {
 fn sort_buf(input: &[u8]) -> Vec<u8> {
     let mut lines = input
         .split(|x| *x == b'\n')
-        .map(|x| Vec::<u8>::from(x))
+        .map(Vec::<u8>::from)
         .collect::<Vec<Vec<u8>>>();
     lines.sort();
     lines.join(&b'\n')
@@ -101,7 +103,8 @@ fn truncate_test(#[case] input: &str, #[case] expect: &str) -> Result<(), Box<dy
         .success()
         .stdout(predicate::str::is_empty())
         .stderr(predicate::eq(format!(
-            "ERROR: '{}' is not a valid IP network, ignoring.\n",
+            "ERROR: <stdin>:1: '{}' is not a valid IP network, ignoring.\n\
+             1 input error(s):\n  1 host bits set\n",
             input
         )));
 
@@ -140,3 +143,862 @@ fn multi_input_test(#[case] path: &str, #[case] args: &str) -> Result<(), Box<dy
 
     Ok(())
 }
+
+#[rstest]
+#[case("10.0.0.0/16", "10.0.4.0/24", "10.0.0.0/22\n10.0.5.0/24\n10.0.6.0/23\n10.0.8.0/21\n10.0.16.0/20\n10.0.32.0/19\n10.0.64.0/18\n10.0.128.0/17\n")]
+#[case("192.0.2.0/24 2001:db8::/32", "192.0.2.0/23", "2001:db8::/32\n")]
+#[case("192.0.2.0/24", "198.51.100.0/24 2001:db8::/32", "192.0.2.0/24\n")]
+fn exclude_test(
+    #[case] input: &str,
+    #[case] exclude: &str,
+    #[case] expect: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exclude_file = NamedTempFile::new("exclude")?;
+    exclude_file.write_str(exclude)?;
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .arg("-x")
+        .arg(exclude_file.path())
+        .write_stdin(input)
+        .assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("10.0.0.0/23 192.0.2.0/24", &["10.0.1.0/24 10.0.2.0/24 192.0.2.128/26"], "10.0.1.0/24\n192.0.2.128/26\n")]
+#[case("10.0.0.0/16 2001:db8::/32", &["10.0.0.0/8", "10.0.128.0/17 10.1.0.0/16", "10.0.0.0/8 2001:db8::/48"], "10.0.128.0/17\n")]
+#[case("10.0.0.0/16", &["192.0.2.0/24"], "")]
+fn intersect_test(
+    #[case] input: &str,
+    #[case] groups: &[&str],
+    #[case] expect: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let mut files = Vec::new();
+    for group in groups {
+        let file = NamedTempFile::new("group")?;
+        file.write_str(group)?;
+        cmd.arg("--intersect").arg(file.path());
+        files.push(file);
+    }
+
+    let assert = cmd.write_stdin(input).assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("128.0.0.0/1 64.0.0.0/2 ::/1", "", "0.0.0.0/2\n8000::/1\n")]
+#[case("128.0.0.0/1 64.0.0.0/2 ::/1", "-4", "0.0.0.0/2\n")]
+#[case("", "-6", "::/0\n")]
+fn complement_test(
+    #[case] input: &str,
+    #[case] args: &str,
+    #[case] expect: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+
+    let assert = cmd
+        .arg("--complement")
+        .args(args.split_whitespace())
+        .write_stdin(input)
+        .assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn complement_universe_test() -> Result<(), Box<dyn Error>> {
+    let universe = NamedTempFile::new("universe")?;
+    universe.write_str("192.0.2.0/24\n2001:db8::/32\n")?;
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .arg("-c")
+        .arg("--universe")
+        .arg(universe.path())
+        .write_stdin("192.0.2.0/25 192.0.2.192/26 10.0.0.0/8 2001:db8::/33")
+        .assert();
+    assert
+        .success()
+        .stdout(predicate::eq("192.0.2.128/26\n2001:db8:8000::/33\n"))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(
+    "192.0.2.1-192.0.2.6",
+    "192.0.2.1/32\n192.0.2.2/31\n192.0.2.4/31\n192.0.2.6/32\n"
+)]
+#[case("192.0.2.0-192.0.2.127 192.0.2.128-192.0.2.255", "192.0.2.0/24\n")]
+#[case(
+    "2001:db8::-2001:db8::ffff:ffff:ffff:ffff 2001:db8:0:1::/64",
+    "2001:db8::/63\n"
+)]
+#[case(
+    "193.0.0.0 - 193.0.7.255\n193.0.8.0 - 193.0.8.255",
+    "193.0.0.0/21\n193.0.8.0/24\n"
+)]
+fn range_input_test(#[case] input: &str, #[case] expect: &str) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+
+    let assert = cmd.write_stdin(input).assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("192.0.2.6-192.0.2.1")]
+#[case("192.0.2.1-2001:db8::1")]
+#[case("192.0.2.1-")]
+fn range_input_invalid_test(#[case] input: &str) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+
+    let assert = cmd.write_stdin(input).assert();
+    assert
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::eq(format!(
+            "ERROR: <stdin>:1: '{}' is not a valid IP range, ignoring.\n\
+             1 input error(s):\n  1 invalid IP range\n",
+            input
+        )));
+
+    Ok(())
+}
+
+#[rstest]
+#[case(
+    "192.0.2.0/24 192.0.3.0/25 198.51.100.7",
+    "192.0.2.0-192.0.3.127\n198.51.100.7-198.51.100.7\n"
+)]
+#[case(
+    "10.0.0.0/24 10.0.1.0/24 2001:db8::/127 2001:db8::2",
+    "10.0.0.0-10.0.1.255\n2001:db8::-2001:db8::2\n"
+)]
+fn range_output_test(#[case] input: &str, #[case] expect: &str) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+
+    let assert = cmd.args(["-F", "range"]).write_stdin(input).assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(
+    "10.0.0.0/16 10.0.0.0/24 10.0.1.0/24 10.1.0.0/16 10.0.0.0/8",
+    "  10.0.0.0/8\n- 10.0.0.0/16\n- 10.0.0.0/24\n- 10.0.1.0/24\n- 10.1.0.0/16\n"
+)]
+#[case(
+    "192.0.2.0/25 192.0.2.128/25 2001:db8::/32",
+    "+ 192.0.2.0/24\n- 192.0.2.0/25\n- 192.0.2.128/25\n  2001:db8::/32\n"
+)]
+fn verbose_test(#[case] input: &str, #[case] expect: &str) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+
+    let assert = cmd.arg("-v").write_stdin(input).assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn error_location_test() -> Result<(), Box<dyn Error>> {
+    let input = NamedTempFile::new("input")?;
+    input.write_str("192.0.2.0/24\n\n198.51.100.0/24 198.51.100.1/24\n")?;
+    let exclude = NamedTempFile::new("exclude")?;
+    exclude.write_str("2001:db8::/129\n")?;
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.arg(input.path()).arg("-x").arg(exclude.path()).assert();
+    assert
+        .success()
+        .stdout(predicate::eq("192.0.2.0/24\n198.51.100.0/24\n"))
+        .stderr(predicate::eq(format!(
+            "ERROR: {}:3: '198.51.100.1/24' is not a valid IP network, ignoring.\n\
+             ERROR: {}:1: '2001:db8::/129' is not a valid IP network, ignoring.\n\
+             2 input error(s):\n  1 invalid IP network\n  1 host bits set\n",
+            input.path().display(),
+            exclude.path().display()
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn strict_test() -> Result<(), Box<dyn Error>> {
+    let input = "192.0.2.0/24 192.0.2.1/24\n192.0.2.300\n198.51.100.1/24 10.0.0.9-10.0.0.1\n";
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.arg("--strict").write_stdin(input).assert();
+    assert
+        .failure()
+        .code(1)
+        .stdout(predicate::str::is_empty())
+        .stderr(
+            "ERROR: <stdin>:1: '192.0.2.1/24' is not a valid IP network.\n\
+             ERROR: <stdin>:2: '192.0.2.300' is not a valid IP network.\n\
+             ERROR: <stdin>:3: '198.51.100.1/24' is not a valid IP network.\n\
+             ERROR: <stdin>:3: '10.0.0.9-10.0.0.1' is not a valid IP range.\n\
+             4 input error(s):\n  1 invalid IP network\n  1 invalid IP range\n  2 host bits set\n",
+        );
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.arg("--strict").write_stdin("192.0.2.0/24").assert();
+    assert
+        .success()
+        .stdout(predicate::eq("192.0.2.0/24\n"))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn max_errors_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--max-errors", "2"])
+        .write_stdin("192.0.2.1/24\n192.0.2.300\n198.51.100.1/24\n")
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::eq(
+            "ERROR: <stdin>:1: '192.0.2.1/24' is not a valid IP network, ignoring.\n\
+         ERROR: <stdin>:2: '192.0.2.300' is not a valid IP network, ignoring.\n\
+         ERROR: Too many errors, aborting.\n\
+         2 input error(s):\n  1 invalid IP network\n  1 host bits set\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn json_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["-F", "json"])
+        .write_stdin("192.0.2.0/25 192.0.2.128/25 2001:db8::/48")
+        .assert();
+    let output = assert.success().stderr(predicate::str::is_empty());
+    let parsed = json::parse(str::from_utf8(&output.get_output().stdout)?)?;
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0]["network"], "192.0.2.0/24");
+    assert_eq!(parsed[0]["family"], "ipv4");
+    assert_eq!(parsed[0]["prefix_length"], 24);
+    assert_eq!(parsed[0]["first"], "192.0.2.0");
+    assert_eq!(parsed[0]["last"], "192.0.2.255");
+    assert_eq!(parsed[0]["addresses"], "256");
+    assert_eq!(parsed[1]["network"], "2001:db8::/48");
+    assert_eq!(parsed[1]["family"], "ipv6");
+    assert_eq!(parsed[1]["addresses"], "1208925819614629174706176");
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["-F", "jsonl"])
+        .write_stdin("192.0.2.0/24 2001:db8::/48")
+        .assert();
+    let output = assert.success().stderr(predicate::str::is_empty());
+    let lines: Vec<_> = str::from_utf8(&output.get_output().stdout)?
+        .lines()
+        .map(json::parse)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["network"], "192.0.2.0/24");
+    assert_eq!(lines[1]["network"], "2001:db8::/48");
+
+    Ok(())
+}
+
+#[rstest]
+#[case("cisco-ios", "", "ip prefix-list AGGREGATE permit 192.0.2.0/24\nipv6 prefix-list AGGREGATE permit 2001:db8::/32\n")]
+#[case("frr", "--list-name PEER --sequence 5", "ip prefix-list PEER seq 5 permit 192.0.2.0/24\nipv6 prefix-list PEER seq 5 permit 2001:db8::/32\n")]
+#[case(
+    "cisco-xr",
+    "",
+    "prefix-set AGGREGATE\n  192.0.2.0/24,\n  2001:db8::/32\nend-set\n"
+)]
+#[case("junos", "--list-name PEER", "set policy-options prefix-list PEER 192.0.2.0/24\nset policy-options prefix-list PEER 2001:db8::/32\n")]
+#[case("arista-eos", "--sequence 10", "ip prefix-list AGGREGATE\n   seq 10 permit 192.0.2.0/24\nipv6 prefix-list AGGREGATE\n   seq 10 permit 2001:db8::/32\n")]
+#[case("bird", "", "define AGGREGATE_v4 = [\n    192.0.2.0/24\n];\ndefine AGGREGATE_v6 = [\n    2001:db8::/32\n];\n")]
+fn prefix_list_test(
+    #[case] format: &str,
+    #[case] args: &str,
+    #[case] expect: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+
+    let assert = cmd
+        .args(["-F", format])
+        .args(args.split_whitespace())
+        .write_stdin("192.0.2.0/25 192.0.2.128/25 2001:db8::/32")
+        .assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn sequence_zero_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["-F", "cisco-ios", "--sequence", "0"])
+        .write_stdin("192.0.2.0/24")
+        .assert();
+    assert
+        .failure()
+        .code(2)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Sequence step must be at least 1"));
+
+    Ok(())
+}
+
+#[rstest]
+#[case("nft", &["--list-name", "BLOCK", "--nft-table", "ip6 fw"], "add set ip6 fw BLOCK_v4 { type ipv4_addr; flags interval; }\nflush set ip6 fw BLOCK_v4\nadd set ip6 fw BLOCK_v6 { type ipv6_addr; flags interval; }\nflush set ip6 fw BLOCK_v6\nadd element ip6 fw BLOCK_v4 {\n    192.0.2.0/24\n}\nadd element ip6 fw BLOCK_v6 {\n    2001:db8::/32\n}\n")]
+#[case("ipset", &[], "create AGGREGATE_v4 hash:net family inet maxelem 65536 -exist\nflush AGGREGATE_v4\nadd AGGREGATE_v4 192.0.2.0/24\ncreate AGGREGATE_v6 hash:net family inet6 maxelem 65536 -exist\nflush AGGREGATE_v6\nadd AGGREGATE_v6 2001:db8::/32\n")]
+#[case("iptables", &[], "iptables -A AGGREGATE -s 192.0.2.0/24 -j DROP\nip6tables -A AGGREGATE -s 2001:db8::/32 -j DROP\n")]
+#[case("pf", &[], "# pf table <AGGREGATE>, load with: pfctl -t AGGREGATE -T replace -f FILE\n192.0.2.0/24\n2001:db8::/32\n")]
+fn firewall_test(
+    #[case] format: &str,
+    #[case] args: &[&str],
+    #[case] expect: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+
+    let assert = cmd
+        .args(["-F", format])
+        .args(args)
+        .write_stdin("192.0.2.0/25 192.0.2.128/25 2001:db8::/32")
+        .assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("nft", "delete element inet filter AGGREGATE_v4 {\n    10.0.0.0/24,\n    10.0.1.0/24\n}\nadd element inet filter AGGREGATE_v4 {\n    10.0.0.0/23\n}\nadd element inet filter AGGREGATE_v6 {\n    fd00::/8\n}\n")]
+#[case("ipset", "add AGGREGATE_v4 10.0.0.0/23\nadd AGGREGATE_v6 fd00::/8\ndel AGGREGATE_v4 10.0.0.0/24\ndel AGGREGATE_v4 10.0.1.0/24\n")]
+fn update_from_test(#[case] format: &str, #[case] expect: &str) -> Result<(), Box<dyn Error>> {
+    let previous = NamedTempFile::new("previous")?;
+    previous.write_str("10.0.0.0/24\n10.0.1.0/24\n192.0.2.0/24\n2001:db8::/32\n")?;
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["-F", format, "--update-from"])
+        .arg(previous.path())
+        .write_stdin("10.0.0.0/24 10.0.1.0/24 192.0.2.0/24 2001:db8::/32 fd00::/8")
+        .assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(
+    "nft",
+    "add set inet filter AGGREGATE_v4 { type ipv4_addr; flags interval; }\n\
+     flush set inet filter AGGREGATE_v4\n\
+     add element inet filter AGGREGATE_v4 {\n    10.0.0.0/24,\n    192.0.2.0/24\n}\n",
+    "delete element inet filter AGGREGATE_v4 {\n    10.0.0.0/24,\n    192.0.2.0/24\n}\n\
+     add element inet filter AGGREGATE_v4 {\n    10.0.0.0/23\n}\n"
+)]
+#[case(
+    "ipset",
+    "create AGGREGATE_v4 hash:net family inet maxelem 65536 -exist\n\
+     flush AGGREGATE_v4\n\
+     add AGGREGATE_v4 10.0.0.0/24\n\
+     add AGGREGATE_v4 192.0.2.0/24\n",
+    "add AGGREGATE_v4 10.0.0.0/23\n\
+     del AGGREGATE_v4 10.0.0.0/24\n\
+     del AGGREGATE_v4 192.0.2.0/24\n"
+)]
+#[case(
+    "nft",
+    "add element inet filter AGGREGATE_v4 { 10.0.0.0/24, 10.1.0.0/24 }\n",
+    "delete element inet filter AGGREGATE_v4 {\n    10.0.0.0/24,\n    10.1.0.0/24\n}\n\
+     add element inet filter AGGREGATE_v4 {\n    10.0.0.0/23\n}\n"
+)]
+#[case(
+    "nft",
+    "add element inet filter OTHER_v4 {\n    10.0.0.0/23\n}\n\
+     add element inet filter AGGREGATE_v4 { 10.0.0.0/24 }\n",
+    "delete element inet filter AGGREGATE_v4 {\n    10.0.0.0/24\n}\n\
+     add element inet filter AGGREGATE_v4 {\n    10.0.0.0/23\n}\n"
+)]
+#[case(
+    "ipset",
+    "add OTHER_v4 10.0.0.0/23\nadd AGGREGATE_v4 10.0.0.0/24\n",
+    "add AGGREGATE_v4 10.0.0.0/23\ndel AGGREGATE_v4 10.0.0.0/24\n"
+)]
+fn update_from_script_test(
+    #[case] format: &str,
+    #[case] script: &str,
+    #[case] expect: &str,
+) -> Result<(), Box<dyn Error>> {
+    let previous = NamedTempFile::new("previous")?;
+    previous.write_str(script)?;
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["-F", format, "--update-from"])
+        .arg(previous.path())
+        .write_stdin("10.0.0.0/23")
+        .assert();
+    assert
+        .success()
+        .stdout(predicate::eq(expect))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn update_from_strict_test() -> Result<(), Box<dyn Error>> {
+    let previous = NamedTempFile::new("previous")?;
+    previous.write_str("10.0.0.0/24\n10.1.1.1/24\n")?;
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["-F", "nft", "--strict", "--update-from"])
+        .arg(previous.path())
+        .write_stdin("10.0.0.0/23")
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::ends_with(
+            "1 input error(s):\n  1 host bits set\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn update_from_format_test() -> Result<(), Box<dyn Error>> {
+    let previous = NamedTempFile::new("previous")?;
+    previous.write_str("10.0.0.0/24\n")?;
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["-F", "pf", "--update-from"])
+        .arg(previous.path())
+        .write_stdin("10.0.0.0/24")
+        .assert();
+    assert.failure().code(2).stdout(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn diff_test() -> Result<(), Box<dyn Error>> {
+    let old = NamedTempFile::new("old")?;
+    old.write_str("10.0.0.0/24 10.0.1.0/24 192.0.2.0/24 2001:db8::/32\n")?;
+    let new = NamedTempFile::new("new")?;
+    new.write_str("10.0.0.0/23 192.0.2.0/25 198.51.100.0/24 2001:db8::/33\n")?;
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.arg("diff").arg(old.path()).arg(new.path()).assert();
+    assert.success().stdout(
+        "- 192.0.2.128/25\n\
+         + 198.51.100.0/24\n\
+         - 2001:db8:8000::/33\n\
+         # IPv4: 1 prefixes removed (128 addresses), 1 prefixes added (256 addresses)\n\
+         # IPv6: 1 prefixes removed (39614081257132168796771975168 addresses), 0 prefixes added (0 addresses)\n",
+    );
+
+    Ok(())
+}
+
+#[test]
+fn diff_options_test() -> Result<(), Box<dyn Error>> {
+    let old = NamedTempFile::new("old")?;
+    old.write_str("10.0.0.0/24 2001:db8::/32\n")?;
+    let new = NamedTempFile::new("new")?;
+    new.write_str("10.0.0.0/23 2001:db8::/32\n")?;
+
+    for args in [["-F", "json"], ["-x", "-"]] {
+        let mut cmd = Command::cargo_bin("rs-aggregate")?;
+        let assert = cmd
+            .args(args)
+            .arg("diff")
+            .arg(old.path())
+            .arg(new.path())
+            .assert();
+        assert
+            .failure()
+            .code(2)
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::str::contains(
+                "can't be used with the diff subcommand",
+            ));
+    }
+
+    // Options applying to how prefixes are read are global
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .arg("diff")
+        .arg("-6")
+        .arg(old.path())
+        .arg(new.path())
+        .assert();
+    assert.success().stdout(predicate::str::starts_with(
+        "# IPv4: 0 prefixes removed (0 addresses), 0 prefixes added (0 addresses)\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn stats_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .arg("--stats")
+        .write_stdin("10.0.0.0/25 10.0.0.128/25 10.0.1.0/24\n2001:db8::/48\n")
+        .assert();
+    assert.success().stdout(
+        "IPv4:\n  prefixes:          3 in, 1 out\n  compression ratio: 3.00:1\n  \
+         addresses:         512 (2 /24s)\n  prefix lengths:\n    len          in       out\n    \
+         /23           0         1\n    /24           1         0\n    /25           2         0\n\
+         IPv6:\n  prefixes:          1 in, 1 out\n  compression ratio: 1.00:1\n  \
+         addresses:         1208925819614629174706176 (1 /48s)\n  prefix lengths:\n    \
+         len          in       out\n    /48           1         1\n",
+    );
+
+    Ok(())
+}
+
+#[test]
+fn max_entries_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--max-entries", "2,1"])
+        .write_stdin("10.0.0.0/24 10.0.2.0/24 10.0.8.0/24 10.0.12.0/24 2001:db8::/48\n")
+        .assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/22\n10.0.8.0/21\n2001:db8::/48\n")
+        .stderr("WARNING: Fitting IPv4 in 2 entries added 2048 addresses.\n");
+
+    Ok(())
+}
+
+#[test]
+fn coverage_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--coverage", "70", "--coverage-prefixlen", "24"])
+        .write_stdin("10.0.0.1 10.0.0.2 10.0.0.3 10.0.0.5 10.0.0.6 10.0.0.7 192.0.2.0/25\n")
+        .assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/29\n192.0.2.0/25\n")
+        .stderr(
+            "WARNING: Approximated 10.0.0.0/29 (75.0% covered), adding 10.0.0.0/32, 10.0.0.4/32.\n",
+        );
+
+    Ok(())
+}
+
+#[test]
+fn min_output_prefixlen_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--min-output-prefixlen", "9,32"])
+        .write_stdin("10.0.0.0/9 10.128.0.0/9 192.0.2.0/24 2001:db8::/32\n")
+        .assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/9\n10.128.0.0/9\n192.0.2.0/24\n2001:db8::/32\n");
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--min-output-prefixlen", "32,64"])
+        .write_stdin("::/0\n")
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stdout(predicate::str::is_empty())
+        .stderr(
+            "ERROR: Splitting would output 18446744073709551616 prefixes, more than --split-limit 1000000, aborting.\n",
+        );
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--min-output-prefixlen", "24", "--max-entries", "1"])
+        .write_stdin("10.0.0.0/22\n")
+        .assert();
+    assert.failure().code(2).stdout(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn widen_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--widen", "24,64"])
+        .write_stdin("192.0.2.1 192.0.2.77 198.51.100.0/25 10.0.0.0/8 2001:db8::1 2001:db8::2\n")
+        .assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/8\n192.0.2.0/24\n198.51.100.0/24\n2001:db8::/64\n");
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["-v", "--widen", "24"])
+        .write_stdin("192.0.2.1 192.0.2.2\n")
+        .assert();
+    assert
+        .success()
+        .stdout("+ 192.0.2.0/24\n- 192.0.2.1/32\n- 192.0.2.2/32\n");
+
+    Ok(())
+}
+
+#[test]
+fn split_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--split", "24,48"])
+        .write_stdin("10.0.0.0/24 10.0.1.0/24 192.0.2.0/25 2001:db8::/47\n")
+        .assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/24\n10.0.1.0/24\n192.0.2.0/25\n2001:db8::/48\n2001:db8:1::/48\n");
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--split", "24", "--split-limit", "255"])
+        .write_stdin("10.0.0.0/16\n")
+        .assert();
+    assert.failure().stdout(predicate::str::is_empty()).stderr(
+        "ERROR: Splitting would output 256 prefixes, more than --split-limit 255, aborting.\n",
+    );
+
+    for format in ["ipset", "range"] {
+        let mut cmd = Command::cargo_bin("rs-aggregate")?;
+        let assert = cmd
+            .args(["--split", "24", "-F", format])
+            .write_stdin("10.0.0.0/23\n")
+            .assert();
+        assert.failure().code(2).stdout(predicate::str::is_empty());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn redundant_only_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .arg("--redundant-only")
+        .write_stdin("10.0.1.0/24 10.0.0.0/24 10.0.0.128/25 192.0.2.0/24 192.0.0.0/16\n")
+        .assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/24\n10.0.1.0/24\n192.0.0.0/16\n");
+
+    Ok(())
+}
+
+#[test]
+fn length_ranges_test() -> Result<(), Box<dyn Error>> {
+    let input = "10.0.0.0/23 10.0.0.0/24 10.0.1.0/24 192.0.2.0/24 2001:db8::/32\n";
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.arg("--length-ranges").write_stdin(input).assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/23 le 24\n192.0.2.0/24\n2001:db8::/32\n");
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--length-ranges", "-F", "cisco-ios"])
+        .write_stdin(input)
+        .assert();
+    assert.success().stdout(
+        "ip prefix-list AGGREGATE permit 10.0.0.0/23 le 24\n\
+         ip prefix-list AGGREGATE permit 192.0.2.0/24\n\
+         ipv6 prefix-list AGGREGATE permit 2001:db8::/32\n",
+    );
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["--length-ranges", "-F", "nft"])
+        .write_stdin(input)
+        .assert();
+    assert.failure().code(2);
+
+    Ok(())
+}
+
+#[test]
+fn prefix_range_input_test() -> Result<(), Box<dyn Error>> {
+    let input = "10.0.0.0/23 le 24;\n\
+                 10.0.2.0/23 prefix-length-range /24-/24\n\
+                 192.0.2.0/24 upto /25 198.51.100.0/24;\n\
+                 2001:db8::/32 ge 48 le 47\n";
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.write_stdin(input).assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/22\n192.0.2.0/24\n198.51.100.0/24\n")
+        .stderr(
+            "ERROR: <stdin>:4: '2001:db8::/32 ge 48 le 47' is not a valid prefix length range, ignoring.\n\
+             1 input error(s):\n  1 invalid prefix length range\n",
+        );
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.arg("--length-ranges").write_stdin(input).assert();
+    assert.success().stdout(
+        "10.0.0.0/22 ge 24 le 24\n10.0.0.0/23 le 24\n192.0.2.0/24 le 25\n198.51.100.0/24\n",
+    );
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .arg("--length-ranges")
+        .write_stdin("10.0.0.0/8 le 32\n0.0.0.0/0 le 32\n")
+        .assert();
+    assert.success().stdout("0.0.0.0/0 le 32\n");
+
+    Ok(())
+}
+
+#[test]
+fn junk_after_prefix_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .write_stdin("10.0.0.0/8 foo 192.0.2.0/24\n1.1.1.1 2 3.3.3.3\n")
+        .assert();
+    assert
+        .success()
+        .stdout("1.1.1.1/32\n3.3.3.3/32\n10.0.0.0/8\n192.0.2.0/24\n")
+        .stderr(
+            "ERROR: <stdin>:1: 'foo' is not a valid IP network, ignoring.\n\
+             ERROR: <stdin>:2: '2' is not a valid IP network, ignoring.\n\
+             2 input error(s):\n  2 invalid IP network\n",
+        );
+
+    Ok(())
+}
+
+#[test]
+fn fib_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .arg("fib")
+        .write_stdin(
+            "10.0.0.0/24 192.0.2.1\n\
+             10.0.1.0/25 192.0.2.1\n\
+             10.0.1.128/25 192.0.2.2\n\
+             \n\
+             10.0.2.0/24\n\
+             2001:db8::/33 eth0\n\
+             2001:db8:8000::/33 eth0\n",
+        )
+        .assert();
+    assert
+        .success()
+        .stdout("10.0.0.0/23 192.0.2.1\n10.0.1.128/25 192.0.2.2\n2001:db8::/32 eth0\n")
+        .stderr(
+            "ERROR: <stdin>:5: '10.0.2.0/24' is not a valid 'prefix next-hop' route, ignoring.\n\
+             1 input error(s):\n  1 invalid route\n",
+        );
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["fib", "--widen", "24"])
+        .write_stdin("192.0.2.1 a\n192.0.2.2 a\n198.51.100.0/25 b\n")
+        .assert();
+    assert
+        .success()
+        .stdout("192.0.2.0/24 a\n198.51.100.0/24 b\n");
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.args(["-x", "-", "fib"]).write_stdin("").assert();
+    assert.failure().code(2).stderr(predicate::str::contains(
+        "can't be used with the fib subcommand",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn origin_test() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .arg("origin")
+        .write_stdin(
+            "10.0.0.0/24 AS64497\n\
+             10.0.1.0/24 64497\n\
+             10.0.2.0/24 64496\n\
+             10.0.3.0/24 as64496\n\
+             10.0.4.0/24 ASx\n\
+             2001:db8::/33 64496\n\
+             2001:db8:8000::/33 64496\n",
+        )
+        .assert();
+    assert
+        .success()
+        .stdout("10.0.2.0/23 64496\n2001:db8::/32 64496\n10.0.0.0/23 64497\n")
+        .stderr(
+            "ERROR: <stdin>:5: '10.0.4.0/24 ASx' is not a valid 'prefix origin-asn' entry, ignoring.\n\
+             1 input error(s):\n  1 invalid origin AS entry\n",
+        );
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd
+        .args(["origin", "--widen", "24"])
+        .write_stdin("192.0.2.1 64496\n192.0.2.2 64497\n")
+        .assert();
+    assert
+        .success()
+        .stdout("192.0.2.0/24 64496\n192.0.2.0/24 64497\n");
+
+    let mut cmd = Command::cargo_bin("rs-aggregate")?;
+    let assert = cmd.args(["-x", "-", "origin"]).write_stdin("").assert();
+    assert.failure().code(2).stderr(predicate::str::contains(
+        "can't be used with the origin subcommand",
+    ));
+
+    Ok(())
+}